@@ -0,0 +1,111 @@
+//! A JSON view of the latest readings, for consumers which do not speak
+//! Prometheus.
+
+use prometheus::core::Collector;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::config::{Exporter, SensorSpec};
+use crate::reload::Active;
+use crate::sampler::{unix_seconds, LatestSample};
+use crate::server::{exporter, sample_gauges, sample_values};
+
+/// Metrics of the readings themselves, rather than quantities derived from
+/// them.
+const READINGS: [&str; 3] = [
+    "meter_temperature_celsius",
+    "meter_pressure_pascals",
+    "meter_humidity_percent",
+];
+
+/// The body of `GET /api/v1/readings`.
+#[derive(Debug, Serialize)]
+pub struct Readings {
+    pub sensors: Vec<SensorReadings>,
+}
+
+/// The latest readings of one sensor.
+#[derive(Debug, Serialize)]
+pub struct SensorReadings {
+    pub name: String,
+    pub bus: String,
+    pub address: String,
+    pub location: String,
+    /// Whether the last measurement succeeded and is recent enough, as
+    /// `bme280_up` reports
+    pub up: bool,
+    /// Why the last measurement failed, if it did
+    pub error: Option<String>,
+    /// Unix time of the latest sample, if there is one
+    pub timestamp: Option<f64>,
+    /// Seconds since the latest sample, if there is one
+    pub age_seconds: Option<f64>,
+    /// Temperature, pressure and humidity after correction, named like
+    /// their metrics without the `meter_` prefix. Empty once the latest
+    /// sample is stale.
+    pub readings: BTreeMap<String, f64>,
+    /// The same before correction
+    pub raw: BTreeMap<String, f64>,
+    /// Quantities calculated from the readings
+    pub derived: BTreeMap<String, f64>,
+}
+
+/// The readings of each sensor in `active`, with the same values as the
+/// metrics of a scrape at the same time.
+pub fn readings(active: &Active) -> Readings {
+    let sensors = active
+        .config
+        .sensors
+        .iter()
+        .zip(&active.sensors)
+        .map(|(spec, latest)| sensor_readings(active, spec, latest))
+        .collect();
+    Readings { sensors }
+}
+
+/// The readings of one sensor in `active`, as in [`readings`].
+pub(crate) fn sensor_readings(
+    active: &Active,
+    spec: &SensorSpec,
+    latest: &LatestSample,
+) -> SensorReadings {
+    let [name, bus, address, location] = latest.labels().values().map(str::to_string);
+    let sample = latest.get();
+    let mut readings = SensorReadings {
+        name,
+        bus,
+        address,
+        location,
+        up: false,
+        error: latest.error(),
+        timestamp: sample.map(|sample| unix_seconds(sample.timestamp)),
+        age_seconds: sample.map(|sample| sample.age().as_secs_f64()),
+        readings: BTreeMap::new(),
+        raw: BTreeMap::new(),
+        derived: BTreeMap::new(),
+    };
+
+    let Some(sample) = latest.fresh(active.config.max_staleness) else {
+        return readings;
+    };
+    readings.up = readings.error.is_none();
+    let values = sample_values(&active.config, spec, &sample);
+    for (gauge, value) in sample_gauges().into_iter().zip(values) {
+        // Quantities which are not exported are not known either.
+        let name = &gauge.desc()[0].fq_name;
+        if value.is_nan() || !active.config.exporters.contains(&exporter(name)) {
+            continue;
+        }
+        let group = if READINGS.contains(&name.as_str()) {
+            &mut readings.readings
+        } else if exporter(name) == Exporter::Raw {
+            &mut readings.raw
+        } else {
+            &mut readings.derived
+        };
+        let name = name.strip_prefix("meter_").unwrap_or(name);
+        let name = name.strip_prefix("raw_").unwrap_or(name);
+        group.insert(name.to_string(), value);
+    }
+    readings
+}
@@ -0,0 +1,205 @@
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::{
+    SensorSpec, DEFAULT_DEV_PATH, DEFAULT_LISTEN_ADDRESS, DEFAULT_MAX_STALENESS,
+    DEFAULT_REINIT_AFTER, DEFAULT_REINIT_BACKOFF, DEFAULT_REINIT_MAX_BACKOFF,
+    DEFAULT_SAMPLE_INTERVAL,
+};
+
+// Flags which are set in the configuration file instead, when one is given.
+const CONFIG_FILE_SETTINGS: [&str; 10] = [
+    "device",
+    "address",
+    "sensors",
+    "listen_address",
+    "sample_interval",
+    "max_staleness",
+    "on_failure",
+    "reinit_after",
+    "reinit_backoff",
+    "reinit_max_backoff",
+];
+
+/// I2C address of the BME280, selected by the level of its SDO pin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SensorAddress {
+    /// 0x76 (SDO pulled to GND)
+    Primary,
+    /// 0x77 (SDO pulled to VDDIO)
+    Secondary,
+}
+
+impl SensorAddress {
+    pub fn value(self) -> u8 {
+        match self {
+            SensorAddress::Primary => 0x76,
+            SensorAddress::Secondary => 0x77,
+        }
+    }
+}
+
+/// How a simulated sensor comes up with its readings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SimulationMode {
+    /// Always report the configured values
+    Constant,
+    /// Drift randomly, starting from the configured values
+    RandomWalk,
+    /// Replay the readings and failures listed in a script file
+    Script,
+}
+
+/// How to answer a scrape while there is no recent reading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureResponse {
+    /// Fail the scrape with 503 Service Unavailable, explaining why
+    Unavailable,
+    /// Succeed with only the exporter's own metrics and `bme280_up 0`
+    Down,
+}
+
+/// A Prometheus exporter for the Bosch BME280 temperature, pressure, and
+/// humidity sensor.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Read the settings from a TOML file instead of the flags below
+    #[arg(short, long, value_name = "FILE", conflicts_with_all = CONFIG_FILE_SETTINGS)]
+    pub config: Option<PathBuf>,
+
+    /// Path of the I2C bus device the sensor is connected to, when no
+    /// `--sensor` is given
+    #[arg(short, long, value_name = "PATH", default_value = DEFAULT_DEV_PATH)]
+    pub device: String,
+
+    /// I2C address of the sensor, when no `--sensor` is given
+    #[arg(short, long, value_enum, default_value_t = SensorAddress::Primary)]
+    pub address: SensorAddress,
+
+    /// A sensor to read, as comma separated `key=value` pairs: `name` is
+    /// required, `device`, `address` and `location` are optional. May be
+    /// repeated to read several sensors
+    #[arg(
+        short,
+        long = "sensor",
+        value_name = "name=NAME[,device=PATH][,address=ADDRESS][,location=TEXT]",
+        value_parser = parse_sensor
+    )]
+    pub sensors: Vec<SensorSpec>,
+
+    /// Address and port to serve metrics on
+    #[arg(short, long, value_name = "ADDR:PORT", default_value = DEFAULT_LISTEN_ADDRESS)]
+    pub listen_address: SocketAddr,
+
+    /// How often to measure, e.g. `500ms`, `5s` or `1m`
+    #[arg(long, value_name = "DURATION", default_value = DEFAULT_SAMPLE_INTERVAL, value_parser = parse_duration)]
+    pub sample_interval: Duration,
+
+    /// Age after which a reading is no longer exported
+    #[arg(long, value_name = "DURATION", default_value = DEFAULT_MAX_STALENESS, value_parser = parse_duration)]
+    pub max_staleness: Duration,
+
+    /// How to answer scrapes when there is no recent reading
+    #[arg(long, value_enum, value_name = "RESPONSE", default_value_t = FailureResponse::Unavailable)]
+    pub on_failure: FailureResponse,
+
+    /// Consecutive failed measurements after which the sensor is reopened
+    /// and initialised again
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_REINIT_AFTER, value_parser = clap::value_parser!(u32).range(1..))]
+    pub reinit_after: u32,
+
+    /// Time to wait before retrying a sensor which could not be opened
+    #[arg(long, value_name = "DURATION", default_value = DEFAULT_REINIT_BACKOFF, value_parser = parse_duration)]
+    pub reinit_backoff: Duration,
+
+    /// Longest time to wait between attempts to open the sensor, which
+    /// doubles after every failed attempt
+    #[arg(long, value_name = "DURATION", default_value = DEFAULT_REINIT_MAX_BACKOFF, value_parser = parse_duration)]
+    pub reinit_max_backoff: Duration,
+
+    /// Serve readings from a simulated sensor instead of the BME280
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub simulate: Option<SimulationMode>,
+
+    /// Temperature reported by the simulated sensor, in degrees Celsius
+    #[arg(long, value_name = "CELSIUS", default_value_t = 20.0)]
+    pub sim_temperature: f64,
+
+    /// Pressure reported by the simulated sensor, in Pascals
+    #[arg(long, value_name = "PASCALS", default_value_t = 101325.0)]
+    pub sim_pressure: f64,
+
+    /// Relative humidity reported by the simulated sensor, in percent
+    #[arg(long, value_name = "PERCENT", default_value_t = 50.0)]
+    pub sim_humidity: f64,
+
+    /// Script replayed by the `script` simulation
+    #[arg(long, value_name = "FILE", required_if_eq("simulate", "script"))]
+    pub sim_script: Option<PathBuf>,
+}
+
+/// Parses a sensor given as comma separated `key=value` pairs.
+pub fn parse_sensor(value: &str) -> Result<SensorSpec, String> {
+    let mut name = None;
+    let mut spec = SensorSpec::new("");
+    let mut seen = Vec::new();
+    for pair in value.split(',') {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected 'key=value', got '{}'", pair))?;
+        if seen.contains(&key) {
+            return Err(format!("sensor setting '{}' is given twice", key));
+        }
+        seen.push(key);
+        match key {
+            "name" if value.is_empty() => return Err("sensor name is empty".to_string()),
+            "name" => name = Some(value.to_string()),
+            "device" => spec.device = value.to_string(),
+            "address" => {
+                spec.address = SensorAddress::from_str(value, true).map_err(|_| {
+                    format!(
+                        "unknown address '{}', expected 'primary' or 'secondary'",
+                        value
+                    )
+                })?
+            }
+            "location" => spec.location = value.to_string(),
+            _ => return Err(format!("unknown sensor setting '{}'", key)),
+        }
+    }
+    spec.name = name.ok_or("sensor has no name")?;
+    Ok(spec)
+}
+
+/// Parses a duration given as a number followed by `ms`, `s`, `m` or `h`.
+/// A bare number is taken as seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a duration", value))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("unknown unit '{}' in '{}'", unit, value)),
+    };
+    let duration =
+        Duration::try_from_secs_f64(seconds).map_err(|_| format!("'{}' is out of range", value))?;
+    // Zero as well as anything rounding down to it, which intervals cannot
+    // be.
+    if duration.is_zero() {
+        return Err(format!("'{}' is not a positive duration", value));
+    }
+    Ok(duration)
+}
@@ -0,0 +1,53 @@
+//! Compression of responses, for scrapes over slow links.
+
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::{self, Write};
+
+pub const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// A content coding responses can be compressed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    /// The zlib format, which HTTP calls `deflate`
+    Deflate,
+}
+
+impl Encoding {
+    /// Every supported encoding, most preferred first.
+    pub const ALL: [Encoding; 2] = [Encoding::Gzip, Encoding::Deflate];
+
+    /// The encoding called `name` in an `Accept-Encoding` header, if it is
+    /// supported.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Value of the `Content-Encoding` header for this encoding.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Encoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
@@ -0,0 +1,587 @@
+//! Settings of the exporter, read from a TOML file or made up from the
+//! command line.
+//!
+//! A configuration file looks like this, with every key optional except for
+//! the names of the sensors:
+//!
+//! ```toml
+//! listen_address = "0.0.0.0:3002"
+//! metric_prefix = ""
+//! sample_interval = "5s"
+//! max_staleness = "30s"
+//! on_failure = "unavailable"
+//! exporters = ["readings", "raw", "summary", "health"]
+//! summary_window = "scrape"
+//! compression_min_size = 1024
+//! stream_min_interval = "1s"
+//! psychrometrics = ["vapor-pressure-deficit", "absolute-humidity"]
+//!
+//! [labels]
+//! site = "home"
+//!
+//! [magnus]
+//! a = 611.2
+//! b = 17.62
+//! c = 243.12
+//!
+//! [recovery]
+//! reinit_after = 3
+//! backoff = "1s"
+//! max_backoff = "5m"
+//!
+//! [[sensor]]
+//! name = "indoor"
+//! device = "/dev/i2c-1"
+//! address = "primary"
+//! location = "office"
+//! mode = "forced"
+//! filter = "16"
+//! standby = "0.5ms"
+//!
+//! altitude = 350.0
+//!
+//! [sensor.oversampling]
+//! temperature = "2x"
+//! pressure = "16x"
+//! humidity = "1x"
+//!
+//! [sensor.correction]
+//! temperature = { offset = -1.5 }
+//! humidity = { raw = [33.5, 74.0], reference = [32.8, 75.3] }
+//!
+//! [sensor.limits]
+//! temperature = { min = -40.0, max = 85.0, max_change_per_minute = 5.0 }
+//!
+//! [sensor.smoothing]
+//! median = 5
+//! ema = 0.3
+//! ```
+
+use anyhow::{bail, Context, Result};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cli::{parse_duration, Args, FailureResponse, SensorAddress};
+use crate::compression;
+use crate::correction::Corrections;
+use crate::derived::Magnus;
+use crate::driver::{Filter, Mode, Oversampling, Settings, Standby};
+use crate::processing::{Limits, Smoothing};
+use crate::psychrometrics::Quantity;
+use crate::recovery::RecoveryPolicy;
+use crate::sensor::{SensorLabels, SENSOR_LABEL_NAMES};
+use crate::server::METRIC_LABEL_NAMES;
+use crate::summary::Window;
+
+pub const DEFAULT_DEV_PATH: &str = "/dev/i2c-1";
+pub const DEFAULT_SENSOR_NAME: &str = "bme280";
+pub const DEFAULT_LISTEN_ADDRESS: &str = "0.0.0.0:3002";
+pub const DEFAULT_SAMPLE_INTERVAL: &str = "5s";
+pub const DEFAULT_MAX_STALENESS: &str = "30s";
+pub const DEFAULT_STREAM_MIN_INTERVAL: &str = "1s";
+pub const DEFAULT_REINIT_AFTER: u32 = 3;
+pub const DEFAULT_REINIT_BACKOFF: &str = "1s";
+pub const DEFAULT_REINIT_MAX_BACKOFF: &str = "5m";
+
+/// A group of metrics which can be turned on or off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Exporter {
+    /// Temperature, pressure and humidity
+    Readings,
+    /// Temperature, pressure and humidity before correction
+    Raw,
+    /// Minimum, maximum and mean temperature, pressure and humidity over the
+    /// summary window
+    Summary,
+    /// The exporter's own metrics on the health of the sensors
+    Health,
+}
+
+/// One sensor to read.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SensorSpec {
+    /// Name identifying the sensor in the `sensor` label
+    pub name: String,
+    /// Path of the I2C bus device the sensor is connected to
+    #[serde(default = "default_device")]
+    pub device: String,
+    #[serde(default = "default_address")]
+    pub address: SensorAddress,
+    /// Free-form description of where the sensor is, for the `location`
+    /// label
+    #[serde(default)]
+    pub location: String,
+    /// Whether the sensor measures when asked to or continuously
+    #[serde(default = "default_mode")]
+    pub mode: Mode,
+    #[serde(default)]
+    pub oversampling: OversamplingSpec,
+    /// Coefficient of the IIR filter applied to temperature and pressure
+    #[serde(default = "default_filter")]
+    pub filter: Filter,
+    /// Time between measurements when the sensor measures on its own
+    #[serde(default = "default_standby")]
+    pub standby: Standby,
+    /// Applied to every reading before it is exported
+    #[serde(default)]
+    pub correction: Corrections,
+    /// Readings outside these, after correction, are rejected
+    #[serde(default)]
+    pub limits: Limits,
+    /// Applied to accepted readings before they are exported
+    #[serde(default)]
+    pub smoothing: Smoothing,
+    /// Height above sea level in metres, for reducing the pressure to sea
+    /// level
+    pub altitude: Option<f64>,
+    /// Current pressure at sea level in Pascals, for estimating the altitude
+    pub sea_level_pressure: Option<f64>,
+}
+
+/// Oversampling of each of the quantities a sensor measures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OversamplingSpec {
+    #[serde(default = "default_temperature_oversampling")]
+    pub temperature: Oversampling,
+    #[serde(default = "default_pressure_oversampling")]
+    pub pressure: Oversampling,
+    #[serde(default = "default_humidity_oversampling")]
+    pub humidity: Oversampling,
+}
+
+impl Default for OversamplingSpec {
+    fn default() -> Self {
+        OversamplingSpec {
+            temperature: default_temperature_oversampling(),
+            pressure: default_pressure_oversampling(),
+            humidity: default_humidity_oversampling(),
+        }
+    }
+}
+
+impl SensorSpec {
+    /// A sensor called `name` at the default device and address.
+    pub fn new(name: &str) -> Self {
+        SensorSpec {
+            name: name.to_string(),
+            device: default_device(),
+            address: default_address(),
+            location: String::new(),
+            mode: default_mode(),
+            oversampling: OversamplingSpec::default(),
+            filter: default_filter(),
+            standby: default_standby(),
+            correction: Corrections::default(),
+            limits: Limits::default(),
+            smoothing: Smoothing::default(),
+            altitude: None,
+            sea_level_pressure: None,
+        }
+    }
+
+    /// How the sensor is to measure.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            mode: self.mode,
+            temperature_oversampling: self.oversampling.temperature,
+            pressure_oversampling: self.oversampling.pressure,
+            humidity_oversampling: self.oversampling.humidity,
+            filter: self.filter,
+            standby: self.standby,
+        }
+    }
+
+    pub fn labels(&self) -> SensorLabels {
+        SensorLabels::new(
+            &self.name,
+            &self.device,
+            self.address.value(),
+            &self.location,
+        )
+    }
+}
+
+fn default_device() -> String {
+    DEFAULT_DEV_PATH.to_string()
+}
+
+fn default_address() -> SensorAddress {
+    SensorAddress::Primary
+}
+
+fn default_mode() -> Mode {
+    Settings::default().mode
+}
+
+fn default_temperature_oversampling() -> Oversampling {
+    Settings::default().temperature_oversampling
+}
+
+fn default_pressure_oversampling() -> Oversampling {
+    Settings::default().pressure_oversampling
+}
+
+fn default_humidity_oversampling() -> Oversampling {
+    Settings::default().humidity_oversampling
+}
+
+fn default_filter() -> Filter {
+    Settings::default().filter
+}
+
+fn default_standby() -> Standby {
+    Settings::default().standby
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecoveryFile {
+    #[serde(default = "default_reinit_after")]
+    reinit_after: u32,
+    #[serde(default = "default_reinit_backoff", deserialize_with = "duration")]
+    backoff: Duration,
+    #[serde(default = "default_reinit_max_backoff", deserialize_with = "duration")]
+    max_backoff: Duration,
+}
+
+impl Default for RecoveryFile {
+    fn default() -> Self {
+        RecoveryFile {
+            reinit_after: default_reinit_after(),
+            backoff: default_reinit_backoff(),
+            max_backoff: default_reinit_max_backoff(),
+        }
+    }
+}
+
+fn default_reinit_after() -> u32 {
+    DEFAULT_REINIT_AFTER
+}
+
+fn default_reinit_backoff() -> Duration {
+    parse_duration(DEFAULT_REINIT_BACKOFF).unwrap()
+}
+
+fn default_reinit_max_backoff() -> Duration {
+    parse_duration(DEFAULT_REINIT_MAX_BACKOFF).unwrap()
+}
+
+/// Layout of the configuration file, which is turned into a [`Config`] once
+/// it has been read.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default = "default_listen_address")]
+    listen_address: SocketAddr,
+    #[serde(default)]
+    metric_prefix: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default = "default_sample_interval", deserialize_with = "duration")]
+    sample_interval: Duration,
+    #[serde(default = "default_max_staleness", deserialize_with = "duration")]
+    max_staleness: Duration,
+    #[serde(default = "default_on_failure")]
+    on_failure: FailureResponse,
+    #[serde(default)]
+    summary_window: Window,
+    #[serde(default = "default_compression_min_size")]
+    compression_min_size: usize,
+    #[serde(default = "default_stream_min_interval", deserialize_with = "duration")]
+    stream_min_interval: Duration,
+    #[serde(default = "default_exporters")]
+    exporters: Vec<Exporter>,
+    #[serde(default)]
+    psychrometrics: Vec<Quantity>,
+    #[serde(default)]
+    magnus: Magnus,
+    #[serde(default)]
+    recovery: RecoveryFile,
+    #[serde(default = "default_sensors", rename = "sensor")]
+    sensors: Vec<SensorSpec>,
+}
+
+fn default_listen_address() -> SocketAddr {
+    DEFAULT_LISTEN_ADDRESS.parse().unwrap()
+}
+
+fn default_sample_interval() -> Duration {
+    parse_duration(DEFAULT_SAMPLE_INTERVAL).unwrap()
+}
+
+fn default_max_staleness() -> Duration {
+    parse_duration(DEFAULT_MAX_STALENESS).unwrap()
+}
+
+fn default_stream_min_interval() -> Duration {
+    parse_duration(DEFAULT_STREAM_MIN_INTERVAL).unwrap()
+}
+
+fn default_on_failure() -> FailureResponse {
+    FailureResponse::Unavailable
+}
+
+fn default_exporters() -> Vec<Exporter> {
+    vec![Exporter::Readings, Exporter::Health]
+}
+
+fn default_compression_min_size() -> usize {
+    compression::DEFAULT_MIN_SIZE
+}
+
+fn default_sensors() -> Vec<SensorSpec> {
+    vec![SensorSpec::new(DEFAULT_SENSOR_NAME)]
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_duration(&value).map_err(D::Error::custom)
+}
+
+/// Everything the exporter needs to know to run.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Address and port to serve metrics on
+    pub listen_address: SocketAddr,
+    /// Prepended to the name of every metric
+    pub metric_prefix: String,
+    /// Labels added to every metric
+    pub labels: BTreeMap<String, String>,
+    /// How often to measure each sensor
+    pub sample_interval: Duration,
+    /// Age after which a reading is no longer exported
+    pub max_staleness: Duration,
+    /// How to answer scrapes when no sensor has a recent reading
+    pub on_failure: FailureResponse,
+    /// Groups of metrics to export
+    pub exporters: Vec<Exporter>,
+    /// The readings the `summary` exporter covers. Every scrape starts a new
+    /// window unless it is fixed.
+    pub summary_window: Window,
+    /// Responses shorter than this many bytes are not compressed
+    pub compression_min_size: usize,
+    /// Least time between two events about the same sensor sent to one
+    /// client of the stream
+    pub stream_min_interval: Duration,
+    /// Psychrometric quantities to export
+    pub psychrometrics: Vec<Quantity>,
+    /// Constants for calculating the dew point and vapour pressures
+    pub magnus: Magnus,
+    pub recovery: RecoveryPolicy,
+    pub sensors: Vec<SensorSpec>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        ConfigFile::default().into()
+    }
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        // An empty file is a valid configuration with every default.
+        toml::from_str("").unwrap()
+    }
+}
+
+impl From<ConfigFile> for Config {
+    fn from(file: ConfigFile) -> Self {
+        Config {
+            listen_address: file.listen_address,
+            metric_prefix: file.metric_prefix,
+            labels: file.labels,
+            sample_interval: file.sample_interval,
+            max_staleness: file.max_staleness,
+            on_failure: file.on_failure,
+            exporters: file.exporters,
+            summary_window: file.summary_window,
+            compression_min_size: file.compression_min_size,
+            stream_min_interval: file.stream_min_interval,
+            psychrometrics: file.psychrometrics,
+            magnus: file.magnus,
+            recovery: RecoveryPolicy {
+                failures: file.recovery.reinit_after,
+                initial_backoff: file.recovery.backoff,
+                max_backoff: file.recovery.max_backoff,
+            },
+            sensors: file.sensors,
+        }
+    }
+}
+
+impl Config {
+    /// Reads and validates the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<Config> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+        Config::parse(&contents)
+            .with_context(|| format!("invalid configuration {}", path.display()))
+    }
+
+    /// Parses and validates the contents of a configuration file.
+    pub fn parse(contents: &str) -> Result<Config> {
+        let file: ConfigFile = toml::from_str(contents)?;
+        let config = Config::from(file);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The configuration given by command-line flags.
+    pub fn from_args(args: &Args) -> Result<Config> {
+        let sensors = if args.sensors.is_empty() {
+            vec![SensorSpec {
+                device: args.device.clone(),
+                address: args.address,
+                ..SensorSpec::new(DEFAULT_SENSOR_NAME)
+            }]
+        } else {
+            args.sensors.clone()
+        };
+        let config = Config {
+            listen_address: args.listen_address,
+            sample_interval: args.sample_interval,
+            max_staleness: args.max_staleness,
+            on_failure: args.on_failure,
+            recovery: RecoveryPolicy {
+                failures: args.reinit_after,
+                initial_backoff: args.reinit_backoff,
+                max_backoff: args.reinit_max_backoff,
+            },
+            sensors,
+            ..Config::default()
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks what the types alone cannot, naming the offending key.
+    fn validate(&self) -> Result<()> {
+        if !self.metric_prefix.is_empty() && !is_metric_name(&self.metric_prefix) {
+            bail!(
+                "metric_prefix: '{}' may only contain letters, digits, '_' and ':', and must not start with a digit",
+                self.metric_prefix
+            );
+        }
+        for name in self.labels.keys() {
+            if !is_label_name(name) {
+                bail!(
+                    "labels.{}: label names may only contain letters, digits and '_', and must not start with a digit or '__'",
+                    name
+                );
+            }
+            if SENSOR_LABEL_NAMES.contains(&name.as_str()) {
+                bail!(
+                    "labels.{}: '{}' is already used to identify sensors",
+                    name,
+                    name
+                );
+            }
+            if METRIC_LABEL_NAMES.contains(&name.as_str()) {
+                bail!(
+                    "labels.{}: '{}' is already used by the exporter's metrics",
+                    name,
+                    name
+                );
+            }
+        }
+        if self.magnus.a <= 0.0 {
+            bail!("magnus.a: must be positive");
+        }
+        if self.magnus.b <= 0.0 {
+            bail!("magnus.b: must be positive");
+        }
+        if self.magnus.c <= 0.0 {
+            bail!("magnus.c: must be positive");
+        }
+        if self.recovery.failures == 0 {
+            bail!("recovery.reinit_after: must be at least 1");
+        }
+        if self.sensors.is_empty() {
+            bail!("sensor: at least one sensor is needed");
+        }
+        for (i, sensor) in self.sensors.iter().enumerate() {
+            if sensor.name.is_empty() {
+                bail!("sensor[{}].name: must not be empty", i);
+            }
+            // The other quantities cannot be compensated without the
+            // temperature.
+            if sensor.oversampling.temperature == Oversampling::Skip {
+                bail!("sensor[{}].oversampling.temperature: must not be 'skip'", i);
+            }
+            if sensor
+                .sea_level_pressure
+                .is_some_and(|pressure| pressure <= 0.0)
+            {
+                bail!("sensor[{}].sea_level_pressure: must be positive", i);
+            }
+            for (quantity, limit) in sensor.limits.each() {
+                if let (Some(min), Some(max)) = (limit.min, limit.max) {
+                    if min >= max {
+                        bail!("sensor[{}].limits.{}: min must be below max", i, quantity);
+                    }
+                }
+                if limit.max_change_per_minute.is_some_and(|rate| rate <= 0.0) {
+                    bail!(
+                        "sensor[{}].limits.{}.max_change_per_minute: must be positive",
+                        i,
+                        quantity
+                    );
+                }
+            }
+            if sensor.smoothing.median == Some(0) {
+                bail!("sensor[{}].smoothing.median: must be at least 1", i);
+            }
+            if sensor
+                .smoothing
+                .ema
+                .is_some_and(|weight| weight.is_nan() || weight <= 0.0 || weight > 1.0)
+            {
+                bail!("sensor[{}].smoothing.ema: must be above 0 and at most 1", i);
+            }
+            for other in &self.sensors[..i] {
+                if sensor.name == other.name {
+                    bail!(
+                        "sensor[{}].name: '{}' is already the name of another sensor",
+                        i,
+                        sensor.name
+                    );
+                }
+                if (&sensor.device, sensor.address) == (&other.device, other.address) {
+                    bail!(
+                        "sensor[{}].address: '{}' is also at {:#04x} on {}",
+                        i,
+                        other.name,
+                        sensor.address.value(),
+                        sensor.device
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    !name.starts_with("__")
+        && chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,105 @@
+//! Linear correction of readings, to match a sensor to a reference
+//! instrument.
+
+use serde::Deserialize;
+
+use crate::sensor::Reading;
+
+/// A linear correction of one quantity, `raw * gain + offset`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "CorrectionFile")]
+pub struct Correction {
+    pub gain: f64,
+    pub offset: f64,
+}
+
+impl Default for Correction {
+    /// Leaves values as they are.
+    fn default() -> Self {
+        Correction {
+            gain: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Correction {
+    /// The correction which turns each `(raw, reference)` pair into its
+    /// reference value, or `None` if both raw values are the same.
+    pub fn two_point(
+        (raw1, reference1): (f64, f64),
+        (raw2, reference2): (f64, f64),
+    ) -> Option<Self> {
+        if raw1 == raw2 {
+            return None;
+        }
+        let gain = (reference2 - reference1) / (raw2 - raw1);
+        Some(Correction {
+            gain,
+            offset: reference1 - raw1 * gain,
+        })
+    }
+
+    pub fn apply(&self, raw: f64) -> f64 {
+        raw * self.gain + self.offset
+    }
+}
+
+/// Layout of a correction in the configuration file: either an `offset`
+/// and optional `gain`, or the `raw` and `reference` values of two
+/// calibration points.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CorrectionFile {
+    offset: Option<f64>,
+    gain: Option<f64>,
+    raw: Option<[f64; 2]>,
+    reference: Option<[f64; 2]>,
+}
+
+impl TryFrom<CorrectionFile> for Correction {
+    type Error = String;
+
+    fn try_from(file: CorrectionFile) -> Result<Self, String> {
+        let correction = match (file.offset, file.gain, file.raw, file.reference) {
+            (None, None, Some(raw), Some(reference)) => {
+                Correction::two_point((raw[0], reference[0]), (raw[1], reference[1]))
+                    .ok_or_else(|| "the two raw values must differ".to_string())?
+            }
+            (offset, gain, None, None) => Correction {
+                gain: gain.unwrap_or(1.0),
+                offset: offset.unwrap_or(0.0),
+            },
+            _ => {
+                return Err(
+                    "expected either `offset` and `gain`, or both `raw` and `reference`"
+                        .to_string(),
+                )
+            }
+        };
+        if correction.gain <= 0.0 {
+            return Err("gain must be positive".to_string());
+        }
+        Ok(correction)
+    }
+}
+
+/// Corrections of each of the quantities a sensor measures.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Corrections {
+    pub temperature: Correction,
+    pub pressure: Correction,
+    pub humidity: Correction,
+}
+
+impl Corrections {
+    /// The corrected `reading`. Humidity stays within 0 to 100%.
+    pub fn apply(&self, reading: &Reading) -> Reading {
+        Reading {
+            temperature: self.temperature.apply(reading.temperature),
+            pressure: self.pressure.apply(reading.pressure),
+            humidity: self.humidity.apply(reading.humidity).clamp(0.0, 100.0),
+        }
+    }
+}
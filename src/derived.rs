@@ -0,0 +1,124 @@
+//! Quantities calculated from the temperature, pressure and humidity a
+//! sensor measures.
+
+use serde::Deserialize;
+
+/// Constants of the Magnus approximation of the saturation vapour pressure
+/// over water, `a * exp(b * t / (c + t))` at temperature `t` in Celsius.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Magnus {
+    /// In Pascals
+    pub a: f64,
+    /// Dimensionless
+    pub b: f64,
+    /// In degrees Celsius
+    pub c: f64,
+}
+
+impl Default for Magnus {
+    /// The constants recommended by Sonntag (1990), for -45 to 60 °C.
+    fn default() -> Self {
+        Magnus {
+            a: 611.2,
+            b: 17.62,
+            c: 243.12,
+        }
+    }
+}
+
+impl Magnus {
+    /// Saturation vapour pressure over water in Pascals at `temperature` in
+    /// Celsius.
+    pub fn saturation_vapor_pressure(&self, temperature: f64) -> f64 {
+        self.a * (self.b * temperature / (self.c + temperature)).exp()
+    }
+
+    /// Dew point in Celsius at `temperature` in Celsius and relative
+    /// `humidity` in percent, or NaN if there is no humidity.
+    pub fn dew_point(&self, temperature: f64, humidity: f64) -> f64 {
+        if humidity.is_nan() || humidity <= 0.0 {
+            return f64::NAN;
+        }
+        let gamma = (humidity / 100.0).ln() + self.b * temperature / (self.c + temperature);
+        self.c * gamma / (self.b - gamma)
+    }
+}
+
+// Standard temperature lapse rate in K/m, and the exponent g·M/(R·L) of the
+// barometric formula.
+const LAPSE_RATE: f64 = 0.0065;
+const BAROMETRIC_EXPONENT: f64 = 5.257;
+const ZERO_CELSIUS: f64 = 273.15;
+
+/// Pressure reduced to sea level (QNH) from `pressure` measured at
+/// `altitude` in metres, with the air column assumed to cool from
+/// `temperature` in Celsius at the standard lapse rate.
+pub fn sea_level_pressure(pressure: f64, temperature: f64, altitude: f64) -> f64 {
+    let kelvin = temperature + ZERO_CELSIUS;
+    pressure * ((kelvin + LAPSE_RATE * altitude) / kelvin).powf(BAROMETRIC_EXPONENT)
+}
+
+/// Altitude in metres at which `pressure` is measured, given the pressure at
+/// sea level; the inverse of [`sea_level_pressure`].
+pub fn altitude(pressure: f64, temperature: f64, sea_level_pressure: f64) -> f64 {
+    let kelvin = temperature + ZERO_CELSIUS;
+    ((sea_level_pressure / pressure).powf(1.0 / BAROMETRIC_EXPONENT) - 1.0) * kelvin / LAPSE_RATE
+}
+
+/// Heat index in Celsius, how hot it feels at `temperature` in Celsius and
+/// relative `humidity` in percent, from the Rothfusz regression used by the
+/// US National Weather Service.
+///
+/// The regression only holds from 26.7 °C (80 °F) upwards, below which this
+/// is NaN.
+pub fn heat_index(temperature: f64, humidity: f64) -> f64 {
+    let t = temperature * 9.0 / 5.0 + 32.0;
+    let rh = humidity;
+    if !(80.0..).contains(&t) {
+        return f64::NAN;
+    }
+    let mut index = -42.379 + 2.04901523 * t + 10.14333127 * rh
+        - 0.22475541 * t * rh
+        - 0.00683783 * t * t
+        - 0.05481717 * rh * rh
+        + 0.00122874 * t * t * rh
+        + 0.00085282 * t * rh * rh
+        - 0.00000199 * t * t * rh * rh;
+    // Adjustments for very dry and very humid air.
+    if rh < 13.0 && t <= 112.0 {
+        index -= (13.0 - rh) / 4.0 * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+    } else if rh > 85.0 && t <= 87.0 {
+        index += (rh - 85.0) / 10.0 * ((87.0 - t) / 5.0);
+    }
+    (index - 32.0) * 5.0 / 9.0
+}
+
+/// Humidex, the Canadian measure of how hot it feels at `temperature` in
+/// Celsius and relative `humidity` in percent, with the vapour pressure from
+/// `magnus`.
+///
+/// It is only meaningful from 20 °C upwards, below which this is NaN.
+pub fn humidex(magnus: &Magnus, temperature: f64, humidity: f64) -> f64 {
+    if !(20.0..).contains(&temperature) {
+        return f64::NAN;
+    }
+    // The formula takes the vapour pressure in hPa.
+    let vapor_pressure = magnus.saturation_vapor_pressure(temperature) * humidity / 100.0 / 100.0;
+    temperature + 5.0 / 9.0 * (vapor_pressure - 10.0)
+}
+
+/// Wet-bulb temperature in Celsius at `temperature` in Celsius and relative
+/// `humidity` in percent, from Stull's approximation at sea level pressure.
+///
+/// The approximation holds from -20 to 50 °C and 5 to 99% humidity, outside
+/// of which this is NaN.
+pub fn wet_bulb_temperature(temperature: f64, humidity: f64) -> f64 {
+    if !(-20.0..=50.0).contains(&temperature) || !(5.0..=99.0).contains(&humidity) {
+        return f64::NAN;
+    }
+    let (t, rh) = (temperature, humidity);
+    t * (0.151977 * (rh + 8.313659).sqrt()).atan() + (t + rh).atan() - (rh - 1.676331).atan()
+        + 0.00391838 * rh.powf(1.5) * (0.023101 * rh).atan()
+        - 4.686035
+}
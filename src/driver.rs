@@ -0,0 +1,562 @@
+//! Register-level access to a BME280 on an I2C bus.
+//!
+//! Unlike the `bme280` crate, this lets the oversampling, IIR filter and
+//! standby settings be chosen, and waits for as long as a measurement with
+//! those settings actually takes. Register addresses and formulas follow the
+//! BME280 datasheet.
+
+use embedded_hal::i2c::blocking::I2c;
+use serde::Deserialize;
+use std::thread;
+use std::time::Duration;
+
+use crate::sensor::Reading;
+
+const CHIP_ID_BME280: u8 = 0x60;
+const CHIP_ID_BMP280: u8 = 0x58;
+
+const REG_CALIB_PT: u8 = 0x88;
+const REG_CHIP_ID: u8 = 0xD0;
+const REG_RESET: u8 = 0xE0;
+const REG_CALIB_H: u8 = 0xE1;
+const REG_CTRL_HUM: u8 = 0xF2;
+const REG_STATUS: u8 = 0xF3;
+const REG_CTRL_MEAS: u8 = 0xF4;
+const REG_CONFIG: u8 = 0xF5;
+const REG_DATA: u8 = 0xF7;
+
+const SOFT_RESET_CMD: u8 = 0xB6;
+const STATUS_MEASURING: u8 = 0x08;
+
+const MODE_MASK: u8 = 0x03;
+const CTRL_HUM_MASK: u8 = 0x07;
+// Bit 1 of config is reserved.
+const CONFIG_MASK: u8 = 0xFD;
+const MODE_SLEEP: u8 = 0x00;
+const MODE_FORCED: u8 = 0x01;
+const MODE_NORMAL: u8 = 0x03;
+
+// The chip needs 2ms to start up after a reset.
+const STARTUP_TIME: Duration = Duration::from_millis(2);
+// How often, and how many more times, to check whether a measurement has
+// finished once it should have.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+const POLL_ATTEMPTS: u32 = 20;
+
+// Value of the data registers when a channel is skipped or after reset.
+const SKIPPED_20_BIT: u32 = 0x80000;
+const SKIPPED_16_BIT: u32 = 0x8000;
+
+const TEMPERATURE_RANGE: (f64, f64) = (-40.0, 85.0);
+const PRESSURE_RANGE: (f64, f64) = (30000.0, 110000.0);
+const HUMIDITY_RANGE: (f64, f64) = (0.0, 100.0);
+
+/// Why talking to the chip failed.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The I2C transaction failed
+    Bus(E),
+    /// The chip ID register holds this value rather than a BME280's or a
+    /// BMP280's
+    UnsupportedChip(u8),
+    /// The raw data cannot be compensated with the chip's calibration
+    Compensation,
+    /// The chip was still busy measuring long after it should have finished
+    Timeout,
+    /// The chip stopped measuring in normal mode, most likely because it
+    /// lost power
+    NotMeasuring,
+    /// The chip no longer has the settings it was initialised with, most
+    /// likely because it lost power
+    SettingsLost,
+    /// A quantity which should have been measured holds the value of a
+    /// skipped one
+    NotMeasured,
+}
+
+/// Oversampling of one of the three measurements, which trades speed and
+/// power for lower noise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum Oversampling {
+    /// Do not measure this quantity at all
+    #[serde(rename = "skip")]
+    Skip,
+    #[serde(rename = "1x")]
+    X1,
+    #[serde(rename = "2x")]
+    X2,
+    #[serde(rename = "4x")]
+    X4,
+    #[serde(rename = "8x")]
+    X8,
+    #[serde(rename = "16x")]
+    X16,
+}
+
+impl Oversampling {
+    fn bits(self) -> u8 {
+        match self {
+            Oversampling::Skip => 0b000,
+            Oversampling::X1 => 0b001,
+            Oversampling::X2 => 0b010,
+            Oversampling::X4 => 0b011,
+            Oversampling::X8 => 0b100,
+            Oversampling::X16 => 0b101,
+        }
+    }
+
+    fn samples(self) -> f64 {
+        match self {
+            Oversampling::Skip => 0.0,
+            Oversampling::X1 => 1.0,
+            Oversampling::X2 => 2.0,
+            Oversampling::X4 => 4.0,
+            Oversampling::X8 => 8.0,
+            Oversampling::X16 => 16.0,
+        }
+    }
+
+    /// The setting as written in the configuration file.
+    pub fn label(self) -> &'static str {
+        match self {
+            Oversampling::Skip => "skip",
+            Oversampling::X1 => "1x",
+            Oversampling::X2 => "2x",
+            Oversampling::X4 => "4x",
+            Oversampling::X8 => "8x",
+            Oversampling::X16 => "16x",
+        }
+    }
+}
+
+/// Coefficient of the IIR filter, which smooths out short disturbances of
+/// temperature and pressure, e.g. from wind or slamming doors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum Filter {
+    #[serde(rename = "off")]
+    Off,
+    #[serde(rename = "2")]
+    X2,
+    #[serde(rename = "4")]
+    X4,
+    #[serde(rename = "8")]
+    X8,
+    #[serde(rename = "16")]
+    X16,
+}
+
+impl Filter {
+    fn bits(self) -> u8 {
+        match self {
+            Filter::Off => 0b000,
+            Filter::X2 => 0b001,
+            Filter::X4 => 0b010,
+            Filter::X8 => 0b011,
+            Filter::X16 => 0b100,
+        }
+    }
+
+    /// The setting as written in the configuration file.
+    pub fn label(self) -> &'static str {
+        match self {
+            Filter::Off => "off",
+            Filter::X2 => "2",
+            Filter::X4 => "4",
+            Filter::X8 => "8",
+            Filter::X16 => "16",
+        }
+    }
+}
+
+/// Time the chip waits between measurements in normal mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum Standby {
+    #[serde(rename = "0.5ms")]
+    Ms0_5,
+    #[serde(rename = "10ms")]
+    Ms10,
+    #[serde(rename = "20ms")]
+    Ms20,
+    #[serde(rename = "62.5ms")]
+    Ms62_5,
+    #[serde(rename = "125ms")]
+    Ms125,
+    #[serde(rename = "250ms")]
+    Ms250,
+    #[serde(rename = "500ms")]
+    Ms500,
+    #[serde(rename = "1000ms")]
+    Ms1000,
+}
+
+impl Standby {
+    fn bits(self) -> u8 {
+        match self {
+            Standby::Ms0_5 => 0b000,
+            Standby::Ms62_5 => 0b001,
+            Standby::Ms125 => 0b010,
+            Standby::Ms250 => 0b011,
+            Standby::Ms500 => 0b100,
+            Standby::Ms1000 => 0b101,
+            Standby::Ms10 => 0b110,
+            Standby::Ms20 => 0b111,
+        }
+    }
+
+    /// The setting as written in the configuration file.
+    pub fn label(self) -> &'static str {
+        match self {
+            Standby::Ms0_5 => "0.5ms",
+            Standby::Ms10 => "10ms",
+            Standby::Ms20 => "20ms",
+            Standby::Ms62_5 => "62.5ms",
+            Standby::Ms125 => "125ms",
+            Standby::Ms250 => "250ms",
+            Standby::Ms500 => "500ms",
+            Standby::Ms1000 => "1000ms",
+        }
+    }
+}
+
+/// When the chip measures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Measure once whenever asked to and sleep in between, which keeps the
+    /// chip from warming itself up
+    Forced,
+    /// Measure continuously, waiting for the standby time between
+    /// measurements, so that reading only fetches the latest result
+    Normal,
+}
+
+impl Mode {
+    fn bits(self) -> u8 {
+        match self {
+            Mode::Forced => MODE_FORCED,
+            Mode::Normal => MODE_NORMAL,
+        }
+    }
+
+    /// The setting as written in the configuration file.
+    pub fn label(self) -> &'static str {
+        match self {
+            Mode::Forced => "forced",
+            Mode::Normal => "normal",
+        }
+    }
+}
+
+/// How the chip measures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub mode: Mode,
+    pub temperature_oversampling: Oversampling,
+    pub pressure_oversampling: Oversampling,
+    pub humidity_oversampling: Oversampling,
+    pub filter: Filter,
+    pub standby: Standby,
+}
+
+impl Default for Settings {
+    /// The settings the `bme280` crate always used.
+    fn default() -> Self {
+        Settings {
+            mode: Mode::Forced,
+            temperature_oversampling: Oversampling::X2,
+            pressure_oversampling: Oversampling::X16,
+            humidity_oversampling: Oversampling::X1,
+            filter: Filter::X16,
+            standby: Standby::Ms0_5,
+        }
+    }
+}
+
+impl Settings {
+    /// Longest time a measurement can take, from section 9.1 of the
+    /// datasheet.
+    pub fn measurement_time(&self) -> Duration {
+        let channel = |oversampling: Oversampling, setup: f64| {
+            if oversampling == Oversampling::Skip {
+                0.0
+            } else {
+                2.3 * oversampling.samples() + setup
+            }
+        };
+        let ms = 1.25
+            + channel(self.temperature_oversampling, 0.0)
+            + channel(self.pressure_oversampling, 0.575)
+            + channel(self.humidity_oversampling, 0.575);
+        Duration::from_secs_f64(ms / 1000.0)
+    }
+
+    fn ctrl_meas(&self, mode: u8) -> u8 {
+        self.temperature_oversampling.bits() << 5 | self.pressure_oversampling.bits() << 2 | mode
+    }
+
+    fn config(&self) -> u8 {
+        self.standby.bits() << 5 | self.filter.bits() << 2
+    }
+}
+
+/// Trimming parameters burnt into each chip, as documented in section 4.2.2
+/// of the BME280 datasheet.
+#[derive(Clone, Copy, Debug)]
+pub struct Calibration {
+    pub dig_t1: u16,
+    pub dig_t2: i16,
+    pub dig_t3: i16,
+    pub dig_p1: u16,
+    pub dig_p2: i16,
+    pub dig_p3: i16,
+    pub dig_p4: i16,
+    pub dig_p5: i16,
+    pub dig_p6: i16,
+    pub dig_p7: i16,
+    pub dig_p8: i16,
+    pub dig_p9: i16,
+    pub dig_h1: u8,
+    pub dig_h2: i16,
+    pub dig_h3: u8,
+    pub dig_h4: i16,
+    pub dig_h5: i16,
+    pub dig_h6: i8,
+}
+
+impl Calibration {
+    /// Decodes the 26 registers from 0x88 and the 7 registers from 0xE1.
+    fn parse(pt: &[u8; 26], h: &[u8; 7]) -> Self {
+        let u16_at = |i: usize| u16::from_le_bytes([pt[i], pt[i + 1]]);
+        let i16_at = |i: usize| i16::from_le_bytes([pt[i], pt[i + 1]]);
+        Calibration {
+            dig_t1: u16_at(0),
+            dig_t2: i16_at(2),
+            dig_t3: i16_at(4),
+            dig_p1: u16_at(6),
+            dig_p2: i16_at(8),
+            dig_p3: i16_at(10),
+            dig_p4: i16_at(12),
+            dig_p5: i16_at(14),
+            dig_p6: i16_at(16),
+            dig_p7: i16_at(18),
+            dig_p8: i16_at(20),
+            dig_p9: i16_at(22),
+            dig_h1: pt[25],
+            dig_h2: i16::from_le_bytes([h[0], h[1]]),
+            dig_h3: h[2],
+            // H4 and H5 are 12 bit values sharing the nibbles of 0xE5.
+            dig_h4: (h[3] as i8 as i16) << 4 | (h[4] & 0x0F) as i16,
+            dig_h5: (h[5] as i8 as i16) << 4 | (h[4] >> 4) as i16,
+            dig_h6: h[6] as i8,
+        }
+    }
+
+    // The compensation formulas below are the floating point versions from
+    // section 8.1 of the datasheet.
+
+    pub(crate) fn t_fine(&self, adc_t: u32) -> f64 {
+        let var1 = (adc_t as f64 / 16384.0 - self.dig_t1 as f64 / 1024.0) * self.dig_t2 as f64;
+        let var2 = adc_t as f64 / 131072.0 - self.dig_t1 as f64 / 8192.0;
+        let var2 = var2 * var2 * self.dig_t3 as f64;
+        // The datasheet keeps t_fine as an integer.
+        ((var1 + var2) as i32) as f64
+    }
+
+    pub(crate) fn temperature(&self, adc_t: u32) -> f64 {
+        self.t_fine(adc_t) / 5120.0
+    }
+
+    /// Compensates `adc_p`, unless nonsensical calibration makes the formula
+    /// divide by zero.
+    pub(crate) fn pressure(&self, adc_p: u32, t_fine: f64) -> Option<f64> {
+        let var1 = t_fine / 2.0 - 64000.0;
+        let var2 = var1 * var1 * self.dig_p6 as f64 / 32768.0;
+        let var2 = var2 + var1 * self.dig_p5 as f64 * 2.0;
+        let var2 = var2 / 4.0 + self.dig_p4 as f64 * 65536.0;
+        let var1 =
+            (self.dig_p3 as f64 * var1 * var1 / 524288.0 + self.dig_p2 as f64 * var1) / 524288.0;
+        let var1 = (1.0 + var1 / 32768.0) * self.dig_p1 as f64;
+        if var1 == 0.0 {
+            return None;
+        }
+        let p = 1048576.0 - adc_p as f64;
+        let p = (p - var2 / 4096.0) * 6250.0 / var1;
+        let var1 = self.dig_p9 as f64 * p * p / 2147483648.0;
+        let var2 = p * self.dig_p8 as f64 / 32768.0;
+        Some(p + (var1 + var2 + self.dig_p7 as f64) / 16.0)
+    }
+
+    pub(crate) fn humidity(&self, adc_h: u32, t_fine: f64) -> f64 {
+        let var1 = t_fine - 76800.0;
+        let var2 = self.dig_h4 as f64 * 64.0 + self.dig_h5 as f64 / 16384.0 * var1;
+        let var3 = adc_h as f64 - var2;
+        let var4 = self.dig_h2 as f64 / 65536.0;
+        let var5 = 1.0 + self.dig_h3 as f64 / 67108864.0 * var1;
+        let var6 = 1.0 + self.dig_h6 as f64 / 67108864.0 * var1 * var5;
+        let var6 = var3 * var4 * (var5 * var6);
+        var6 * (1.0 - self.dig_h1 as f64 * var6 / 524288.0)
+    }
+
+    /// Turns raw ADC values into a reading, clamped to the chip's operating
+    /// range. Quantities which were not measured are NaN, as is pressure
+    /// which the calibration cannot compensate.
+    fn compensate(&self, adc_t: u32, adc_p: Option<u32>, adc_h: Option<u32>) -> Reading {
+        let t_fine = self.t_fine(adc_t);
+        let clamp = |value: f64, (min, max): (f64, f64)| value.clamp(min, max);
+        Reading {
+            temperature: clamp(self.temperature(adc_t), TEMPERATURE_RANGE),
+            pressure: adc_p
+                .and_then(|adc| self.pressure(adc, t_fine))
+                .map_or(f64::NAN, |pressure| clamp(pressure, PRESSURE_RANGE)),
+            humidity: adc_h.map_or(f64::NAN, |adc| {
+                clamp(self.humidity(adc, t_fine), HUMIDITY_RANGE)
+            }),
+        }
+    }
+}
+
+/// A BME280 at `address` on an I2C bus, or a BMP280, which is the same
+/// without humidity.
+pub struct Bme280<I2C> {
+    i2c: I2C,
+    address: u8,
+    settings: Settings,
+    calibration: Option<Calibration>,
+    /// False for a BMP280, which lacks ctrl_hum as well as the humidity
+    /// data registers
+    has_humidity: bool,
+}
+
+impl<I2C: I2c> Bme280<I2C> {
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        Bme280 {
+            i2c,
+            address,
+            settings: Settings::default(),
+            calibration: None,
+            has_humidity: true,
+        }
+    }
+
+    /// Checks that the chip is a BME280 or BMP280, resets it, reads its
+    /// calibration and applies `settings`. In forced mode the chip is left
+    /// asleep, in normal mode it has finished its first measurement on
+    /// return.
+    ///
+    /// A BMP280 never measures humidity, whatever `settings` say.
+    pub fn init(&mut self, mut settings: Settings) -> Result<(), Error<I2C::Error>> {
+        self.has_humidity = match self.read_register(REG_CHIP_ID)? {
+            CHIP_ID_BME280 => true,
+            CHIP_ID_BMP280 => false,
+            chip_id => return Err(Error::UnsupportedChip(chip_id)),
+        };
+        if !self.has_humidity {
+            settings.humidity_oversampling = Oversampling::Skip;
+        }
+
+        self.write_register(REG_RESET, SOFT_RESET_CMD)?;
+        thread::sleep(STARTUP_TIME);
+
+        let mut pt = [0; 26];
+        self.read(REG_CALIB_PT, &mut pt)?;
+        let mut h = [0; 7];
+        self.read(REG_CALIB_H, &mut h)?;
+        self.calibration = Some(Calibration::parse(&pt, &h));
+
+        // Changes to ctrl_hum only take effect once ctrl_meas is written, and
+        // writes to config may be ignored outside sleep mode, so ctrl_meas,
+        // which starts normal mode, comes last.
+        if self.has_humidity {
+            self.write_register(REG_CTRL_HUM, settings.humidity_oversampling.bits())?;
+        }
+        self.write_register(REG_CONFIG, settings.config())?;
+        self.settings = settings;
+        match settings.mode {
+            Mode::Forced => self.write_register(REG_CTRL_MEAS, settings.ctrl_meas(MODE_SLEEP)),
+            Mode::Normal => {
+                self.write_register(REG_CTRL_MEAS, settings.ctrl_meas(settings.mode.bits()))?;
+                self.wait_for_measurement()
+            }
+        }
+    }
+
+    /// In forced mode, takes a single measurement after which the chip goes
+    /// back to sleep. In normal mode, reads the result of the latest
+    /// measurement.
+    pub fn measure(&mut self) -> Result<Reading, Error<I2C::Error>> {
+        let calibration = self.calibration.ok_or(Error::Compensation)?;
+
+        // After a power cycle the chip sleeps with every setting cleared, so
+        // that humidity is skipped and the data registers keep their reset
+        // values, none of which must pass as a reading.
+        let mut control = [0; 4];
+        self.read(REG_CTRL_HUM, &mut control)?;
+        let [ctrl_hum, _status, ctrl_meas, config] = control;
+        let humidity_lost = self.has_humidity
+            && ctrl_hum & CTRL_HUM_MASK != self.settings.humidity_oversampling.bits();
+        if humidity_lost || config & CONFIG_MASK != self.settings.config() {
+            return Err(Error::SettingsLost);
+        }
+        match self.settings.mode {
+            Mode::Forced => {
+                let ctrl_meas = self.settings.ctrl_meas(self.settings.mode.bits());
+                self.write_register(REG_CTRL_MEAS, ctrl_meas)?;
+                self.wait_for_measurement()?;
+            }
+            Mode::Normal => {
+                if ctrl_meas & MODE_MASK != MODE_NORMAL {
+                    return Err(Error::NotMeasuring);
+                }
+            }
+        }
+
+        let mut data = [0; 8];
+        self.read(REG_DATA, &mut data)?;
+        let adc_p = (data[0] as u32) << 12 | (data[1] as u32) << 4 | (data[2] as u32) >> 4;
+        let adc_t = (data[3] as u32) << 12 | (data[4] as u32) << 4 | (data[5] as u32) >> 4;
+        let adc_h = (data[6] as u32) << 8 | data[7] as u32;
+
+        let measured = |oversampling, adc| (oversampling != Oversampling::Skip).then_some(adc);
+        let adc_p = measured(self.settings.pressure_oversampling, adc_p);
+        let adc_h = measured(self.settings.humidity_oversampling, adc_h);
+        // The chip may still have been reset between checking its settings
+        // and converting.
+        if adc_t == SKIPPED_20_BIT || adc_p == Some(SKIPPED_20_BIT) || adc_h == Some(SKIPPED_16_BIT)
+        {
+            return Err(Error::NotMeasured);
+        }
+        Ok(calibration.compensate(adc_t, adc_p, adc_h))
+    }
+
+    /// Waits until a measurement which has just started is finished.
+    fn wait_for_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        thread::sleep(self.settings.measurement_time());
+        let mut attempts = 0;
+        while self.read_register(REG_STATUS)? & STATUS_MEASURING != 0 {
+            attempts += 1;
+            if attempts > POLL_ATTEMPTS {
+                return Err(Error::Timeout);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, register: u8, buffer: &mut [u8]) -> Result<(), Error<I2C::Error>> {
+        self.i2c
+            .write_read(self.address, &[register], buffer)
+            .map_err(Error::Bus)
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, Error<I2C::Error>> {
+        let mut value = [0];
+        self.read(register, &mut value)?;
+        Ok(value[0])
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), Error<I2C::Error>> {
+        self.i2c
+            .write(self.address, &[register, value])
+            .map_err(Error::Bus)
+    }
+}
@@ -0,0 +1,423 @@
+//! An in-process stand-in for a BME280 on an I2C bus.
+//!
+//! [`EmulatedBme280`] implements the `embedded-hal` I2C traits and answers
+//! register reads and writes the way the real chip does, so it can be handed
+//! to [`Bme280`](crate::driver::Bme280) in place of an `I2cdev`. The physical conditions
+//! it reports are set with [`EmulatedBme280::set_reading`]; they are turned
+//! into raw ADC values using the chip's fixed calibration parameters, which
+//! means the driver's compensation code is exercised exactly as on hardware.
+
+use embedded_hal::i2c::blocking::{I2c, Operation};
+use embedded_hal::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, SevenBitAddress};
+use std::sync::{Arc, Mutex};
+
+pub use crate::driver::Calibration;
+use crate::sensor::Reading;
+
+pub const CHIP_ID: u8 = 0x60;
+
+const REG_CALIB_PT: u8 = 0x88;
+const REG_CALIB_H1: u8 = 0xA1;
+const REG_CHIP_ID: u8 = 0xD0;
+const REG_RESET: u8 = 0xE0;
+const REG_CALIB_H: u8 = 0xE1;
+const REG_CTRL_HUM: u8 = 0xF2;
+const REG_STATUS: u8 = 0xF3;
+const REG_CTRL_MEAS: u8 = 0xF4;
+const REG_CONFIG: u8 = 0xF5;
+const REG_DATA: u8 = 0xF7;
+
+const SOFT_RESET_CMD: u8 = 0xB6;
+
+const MODE_MASK: u8 = 0x03;
+const MODE_SLEEP: u8 = 0x00;
+const MODE_NORMAL: u8 = 0x03;
+
+// Value of the data registers when a channel is skipped or after reset.
+const SKIPPED_20_BIT: u32 = 0x80000;
+const SKIPPED_16_BIT: u32 = 0x8000;
+
+impl Default for Calibration {
+    /// Parameters read from a real breakout board. Those for temperature
+    /// and pressure are also the worked example of the BMP280 datasheet.
+    fn default() -> Self {
+        Calibration {
+            dig_t1: 27504,
+            dig_t2: 26435,
+            dig_t3: -1000,
+            dig_p1: 36477,
+            dig_p2: -10685,
+            dig_p3: 3024,
+            dig_p4: 2855,
+            dig_p5: 140,
+            dig_p6: -7,
+            dig_p7: 15500,
+            dig_p8: -14600,
+            dig_p9: 6000,
+            dig_h1: 75,
+            dig_h2: 362,
+            dig_h3: 0,
+            dig_h4: 313,
+            dig_h5: 50,
+            dig_h6: 30,
+        }
+    }
+}
+
+impl Calibration {
+    fn write_to(&self, registers: &mut [u8; 256]) {
+        let pt = [
+            self.dig_t1.to_le_bytes(),
+            self.dig_t2.to_le_bytes(),
+            self.dig_t3.to_le_bytes(),
+            self.dig_p1.to_le_bytes(),
+            self.dig_p2.to_le_bytes(),
+            self.dig_p3.to_le_bytes(),
+            self.dig_p4.to_le_bytes(),
+            self.dig_p5.to_le_bytes(),
+            self.dig_p6.to_le_bytes(),
+            self.dig_p7.to_le_bytes(),
+            self.dig_p8.to_le_bytes(),
+            self.dig_p9.to_le_bytes(),
+        ];
+        let start = REG_CALIB_PT as usize;
+        registers[start..start + 24].copy_from_slice(&pt.concat());
+        registers[REG_CALIB_H1 as usize] = self.dig_h1;
+
+        let h4 = self.dig_h4 as u16;
+        let h5 = self.dig_h5 as u16;
+        let h2 = self.dig_h2.to_le_bytes();
+        let start = REG_CALIB_H as usize;
+        registers[start..start + 7].copy_from_slice(&[
+            h2[0],
+            h2[1],
+            self.dig_h3,
+            (h4 >> 4) as u8,
+            ((h4 & 0x0F) | ((h5 & 0x0F) << 4)) as u8,
+            (h5 >> 4) as u8,
+            self.dig_h6 as u8,
+        ]);
+    }
+
+    /// Raw ADC values which the compensation formulas turn into `reading`.
+    fn raw(&self, reading: &Reading) -> (u32, u32, u32) {
+        let adc_t = invert(|adc| self.temperature(adc), reading.temperature, 20);
+        let t_fine = self.t_fine(adc_t);
+        let adc_p = invert(
+            |adc| self.pressure(adc, t_fine).unwrap_or(f64::NAN),
+            reading.pressure,
+            20,
+        );
+        let adc_h = invert(|adc| self.humidity(adc, t_fine), reading.humidity, 16);
+        (adc_t, adc_p, adc_h)
+    }
+}
+
+/// Finds the ADC value in `0..2^bits` for which the monotonic `compensate`
+/// comes closest to `target`.
+fn invert(compensate: impl Fn(u32) -> f64, target: f64, bits: u32) -> u32 {
+    let (mut low, mut high) = (0, (1 << bits) - 1);
+    let increasing = compensate(high) > compensate(low);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if (compensate(mid) < target) == increasing {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    if (compensate(low) - target).abs() <= (compensate(high) - target).abs() {
+        low
+    } else {
+        high
+    }
+}
+
+struct State {
+    address: SevenBitAddress,
+    registers: [u8; 256],
+    pointer: u8,
+    calibration: Calibration,
+    reading: Reading,
+    /// ADC values to report instead of those for `reading`
+    raw: Option<(u32, u32, u32)>,
+    connected: bool,
+    failures: usize,
+    transactions: usize,
+}
+
+impl State {
+    fn reset(&mut self) {
+        self.registers[REG_CTRL_HUM as usize] = 0;
+        self.registers[REG_STATUS as usize] = 0;
+        self.registers[REG_CTRL_MEAS as usize] = 0;
+        self.registers[REG_CONFIG as usize] = 0;
+        self.store_data(SKIPPED_20_BIT, SKIPPED_20_BIT, SKIPPED_16_BIT);
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        match register {
+            REG_RESET if value == SOFT_RESET_CMD => self.reset(),
+            REG_CTRL_HUM | REG_CONFIG => self.registers[register as usize] = value,
+            REG_CTRL_MEAS => {
+                self.registers[register as usize] = value;
+                if value & MODE_MASK != MODE_SLEEP {
+                    self.convert();
+                }
+            }
+            // Everything else is read-only.
+            _ => {}
+        }
+    }
+
+    /// Runs a conversion, as the chip does on entering forced or normal mode.
+    fn convert(&mut self) {
+        let ctrl_meas = self.registers[REG_CTRL_MEAS as usize];
+        let osrs_t = ctrl_meas >> 5;
+        let osrs_p = (ctrl_meas >> 2) & 0x07;
+        let osrs_h = self.registers[REG_CTRL_HUM as usize] & 0x07;
+
+        let (adc_t, adc_p, adc_h) = self
+            .raw
+            .unwrap_or_else(|| self.calibration.raw(&self.reading));
+        self.store_data(
+            if osrs_t == 0 { SKIPPED_20_BIT } else { adc_t },
+            if osrs_p == 0 { SKIPPED_20_BIT } else { adc_p },
+            if osrs_h == 0 { SKIPPED_16_BIT } else { adc_h },
+        );
+
+        // Forced mode drops back to sleep once the conversion is done.
+        if ctrl_meas & MODE_MASK != MODE_NORMAL {
+            self.registers[REG_CTRL_MEAS as usize] = ctrl_meas & !MODE_MASK;
+        }
+    }
+
+    fn store_data(&mut self, adc_t: u32, adc_p: u32, adc_h: u32) {
+        let start = REG_DATA as usize;
+        self.registers[start..start + 8].copy_from_slice(&[
+            (adc_p >> 12) as u8,
+            (adc_p >> 4) as u8,
+            (adc_p << 4) as u8,
+            (adc_t >> 12) as u8,
+            (adc_t >> 4) as u8,
+            (adc_t << 4) as u8,
+            (adc_h >> 8) as u8,
+            adc_h as u8,
+        ]);
+    }
+
+    fn check(&mut self, address: SevenBitAddress) -> Result<(), ErrorKind> {
+        self.transactions += 1;
+        if !self.connected || address != self.address {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+        if self.failures > 0 {
+            self.failures -= 1;
+            return Err(ErrorKind::Bus);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) {
+        // In normal mode the chip measures continuously; the emulation
+        // skips the standby time and measures whenever the data is read.
+        let ctrl_meas = self.registers[REG_CTRL_MEAS as usize];
+        if self.pointer == REG_DATA && ctrl_meas & MODE_MASK == MODE_NORMAL {
+            self.convert();
+        }
+        for byte in buffer {
+            *byte = self.registers[self.pointer as usize];
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // A lone byte selects the register to read from; otherwise the bytes
+        // are pairs of a register address and the value to write to it.
+        match bytes {
+            [] => {}
+            [register] => self.pointer = *register,
+            _ => {
+                for pair in bytes.chunks_exact(2) {
+                    self.pointer = pair[0];
+                    self.write_register(pair[0], pair[1]);
+                }
+            }
+        }
+    }
+}
+
+/// An emulated BME280, see the [module documentation](self).
+///
+/// Clones share the same device, so a test can keep a handle to change the
+/// conditions or inject faults after giving the bus to the driver.
+#[derive(Clone)]
+pub struct EmulatedBme280 {
+    state: Arc<Mutex<State>>,
+}
+
+impl EmulatedBme280 {
+    /// Creates a powered-up chip answering at `address` and reporting `reading`.
+    pub fn new(address: SevenBitAddress, reading: Reading) -> Self {
+        Self::with_calibration(address, reading, Calibration::default())
+    }
+
+    pub fn with_calibration(
+        address: SevenBitAddress,
+        reading: Reading,
+        calibration: Calibration,
+    ) -> Self {
+        let mut state = State {
+            address,
+            registers: [0; 256],
+            pointer: 0,
+            calibration,
+            reading,
+            raw: None,
+            connected: true,
+            failures: 0,
+            transactions: 0,
+        };
+        state.registers[REG_CHIP_ID as usize] = CHIP_ID;
+        calibration.write_to(&mut state.registers);
+        state.reset();
+        EmulatedBme280 {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        // A panicking test thread must not take other tests down with it.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sets the conditions reported by subsequent conversions.
+    pub fn set_reading(&self, reading: Reading) {
+        let mut state = self.state();
+        state.reading = reading;
+        state.raw = None;
+    }
+
+    /// Makes subsequent conversions report these raw ADC values, until the
+    /// next [`set_reading`](Self::set_reading). Unlike readings, these do
+    /// not depend on the driver's compensation, so it can be checked
+    /// against known results.
+    pub fn set_raw(&self, adc_t: u32, adc_p: u32, adc_h: u32) {
+        self.state().raw = Some((adc_t, adc_p, adc_h));
+    }
+
+    /// Overrides the value of the chip ID register.
+    pub fn set_chip_id(&self, chip_id: u8) {
+        self.state().registers[REG_CHIP_ID as usize] = chip_id;
+    }
+
+    /// Simulates unplugging (`false`) or reconnecting (`true`) the chip. A
+    /// disconnected chip does not acknowledge its address.
+    ///
+    /// Reconnecting power cycles the chip, resetting its configuration.
+    pub fn set_connected(&self, connected: bool) {
+        let mut state = self.state();
+        if connected && !state.connected {
+            state.reset();
+        }
+        state.connected = connected;
+    }
+
+    /// Makes the next `count` transactions fail with a bus error.
+    pub fn fail_next(&self, count: usize) {
+        self.state().failures = count;
+    }
+
+    /// Returns the current value of `register`.
+    pub fn register(&self, register: u8) -> u8 {
+        self.state().registers[register as usize]
+    }
+
+    /// Number of transactions addressed to the bus so far, including failed
+    /// ones.
+    pub fn transactions(&self) -> usize {
+        self.state().transactions
+    }
+}
+
+impl ErrorType for EmulatedBme280 {
+    type Error = ErrorKind;
+}
+
+impl I2c for EmulatedBme280 {
+    fn read(&mut self, address: SevenBitAddress, buffer: &mut [u8]) -> Result<(), ErrorKind> {
+        let mut state = self.state();
+        state.check(address)?;
+        state.read(buffer);
+        Ok(())
+    }
+
+    fn write(&mut self, address: SevenBitAddress, bytes: &[u8]) -> Result<(), ErrorKind> {
+        let mut state = self.state();
+        state.check(address)?;
+        state.write(bytes);
+        Ok(())
+    }
+
+    fn write_iter<B>(&mut self, address: SevenBitAddress, bytes: B) -> Result<(), ErrorKind>
+    where
+        B: IntoIterator<Item = u8>,
+    {
+        let bytes: Vec<u8> = bytes.into_iter().collect();
+        self.write(address, &bytes)
+    }
+
+    fn write_read(
+        &mut self,
+        address: SevenBitAddress,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), ErrorKind> {
+        let mut state = self.state();
+        state.check(address)?;
+        state.write(bytes);
+        state.read(buffer);
+        Ok(())
+    }
+
+    fn write_iter_read<B>(
+        &mut self,
+        address: SevenBitAddress,
+        bytes: B,
+        buffer: &mut [u8],
+    ) -> Result<(), ErrorKind>
+    where
+        B: IntoIterator<Item = u8>,
+    {
+        let bytes: Vec<u8> = bytes.into_iter().collect();
+        self.write_read(address, &bytes, buffer)
+    }
+
+    fn transaction<'a>(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'a>],
+    ) -> Result<(), ErrorKind> {
+        let mut state = self.state();
+        state.check(address)?;
+        for operation in operations {
+            match operation {
+                Operation::Read(buffer) => state.read(buffer),
+                Operation::Write(bytes) => state.write(bytes),
+            }
+        }
+        Ok(())
+    }
+
+    fn transaction_iter<'a, O>(
+        &mut self,
+        address: SevenBitAddress,
+        operations: O,
+    ) -> Result<(), ErrorKind>
+    where
+        O: IntoIterator<Item = Operation<'a>>,
+    {
+        let mut operations: Vec<_> = operations.into_iter().collect();
+        self.transaction(address, &mut operations)
+    }
+}
@@ -0,0 +1,21 @@
+pub mod api;
+pub mod cli;
+pub mod compression;
+pub mod config;
+pub mod correction;
+pub mod derived;
+pub mod driver;
+#[cfg(feature = "emulator")]
+pub mod emulator;
+pub mod openmetrics;
+pub mod processing;
+pub mod psychrometrics;
+pub mod recovery;
+pub mod reload;
+pub mod sampler;
+pub mod sensor;
+pub mod server;
+pub mod sim;
+pub mod stream;
+pub mod summary;
+pub mod tendency;
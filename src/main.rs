@@ -1,128 +1,78 @@
-use anyhow::{anyhow, Result};
-use bme280::i2c::BME280;
-use hyper::server::conn::Http;
-use hyper::service::Service;
-use hyper::{Body, Method, Request, Response, StatusCode};
-use lazy_static::lazy_static;
-use linux_embedded_hal::{Delay, I2CError, I2cdev};
-use prometheus::{register_gauge, Encoder, Gauge, TextEncoder};
+use anyhow::Result;
+use clap::{CommandFactory, Parser};
+use std::sync::Arc;
 use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
 
-use bme280::Measurements;
-use std::future::Future;
-use std::net::SocketAddr;
-use std::pin::Pin;
-use std::sync::{Arc, Mutex};
-use std::task::{Context, Poll};
-
-lazy_static! {
-    static ref TEMPERATURE_GAUGE: Gauge = register_gauge!(
-        "meter_temperature_celsius",
-        "Ambient temperature in Celsius"
-    )
-    .unwrap();
-    static ref PRESSURE_GAUGE: Gauge =
-        register_gauge!("meter_pressure_pascals", "Atmospheric pressure in Pascals").unwrap();
-    static ref HUMIDITY_GAUGE: Gauge =
-        register_gauge!("meter_humidity_percent", "Relative humidity in %").unwrap();
-}
-
-const DEFAULT_DEV_PATH: &str = "/dev/i2c-1";
-
-#[derive(Clone)]
-struct TempServer {
-    bme280: Arc<Mutex<BME280<I2cdev>>>,
-}
-
-impl TempServer {
-    fn new() -> Result<TempServer> {
-        let i2c_bus = I2cdev::new(DEFAULT_DEV_PATH)?;
-        let mut bme280 = BME280::new_primary(i2c_bus);
-
-        let mut delay = Delay;
-
-        bme280
-            .init(&mut delay)
-            .map_err(|e| anyhow!("unable to init: {:?}", e))?;
-        Ok(TempServer {
-            bme280: Arc::new(Mutex::new(bme280)),
-        })
-    }
-
-    fn measure(&self) -> Result<Measurements<I2CError>> {
-        let mut delay = Delay;
-        let measurement = self
-            .bme280
-            .lock()
-            .map_err(|e| anyhow!("lock poisoned: {:?}", e))?
-            .measure(&mut delay)
-            .map_err(|e| anyhow!("unable to measure: {:?}", e))?;
-        Ok(measurement)
-    }
-}
-
-impl Service<Request<Body>> for TempServer {
-    type Response = Response<Body>;
-    type Error = anyhow::Error;
-    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
-
-    fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
-        Poll::Ready(Ok(()))
-    }
-
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
-        match (req.method(), req.uri().path()) {
-            (&Method::GET, "/metrics") => {
-                let measurements = self.measure();
-
-                if measurements.is_err() {
-                    return Box::pin(async { Err(anyhow!("unable to measure")) });
-                }
-                let measurements = measurements.unwrap();
-
-                TEMPERATURE_GAUGE.set(measurements.temperature.into());
-                PRESSURE_GAUGE.set(measurements.pressure.into());
-                HUMIDITY_GAUGE.set(measurements.humidity.into());
-
-                let mut buffer = Vec::new();
-                let encoder = TextEncoder::new();
-
-                let metric_families = prometheus::gather();
-                encoder
-                    .encode(&metric_families, &mut buffer)
-                    .expect("encoding failed");
-
-                let buffer = buffer.clone();
-
-                Box::pin(async { Ok(Response::builder().body(Body::from(buffer)).unwrap()) })
-            }
-            _ => Box::pin(async {
-                Ok(Response::builder()
-                    .status(StatusCode::NOT_FOUND)
-                    .body(Body::empty())
-                    .unwrap())
-            }),
-        }
-    }
-}
+use prometheus_bme280_exporter::cli::{Args, SimulationMode};
+use prometheus_bme280_exporter::config::{Config, SensorSpec};
+use prometheus_bme280_exporter::recovery::RecoveringSensor;
+use prometheus_bme280_exporter::reload::ActiveConfig;
+use prometheus_bme280_exporter::sampler;
+use prometheus_bme280_exporter::sensor::{Bme280Sensor, Reading};
+use prometheus_bme280_exporter::server::{self, TempServer};
+use prometheus_bme280_exporter::sim::SimulatedSensor;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3002));
-
-    let server = TempServer::new()?;
-
-    let listener = TcpListener::bind(addr).await?;
-    println!("Listening on http://{}", addr);
+    let args = Args::parse();
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::from_args(&args).unwrap_or_else(|err| {
+            Args::command()
+                .error(clap::error::ErrorKind::ArgumentConflict, err)
+                .exit()
+        }),
+    };
+
+    let listener = TcpListener::bind(config.listen_address).await?;
+    println!("Listening on http://{}", config.listen_address);
+
+    let initial = Reading {
+        temperature: args.sim_temperature,
+        pressure: args.sim_pressure,
+        humidity: args.sim_humidity,
+    };
+    // Every sensor is simulated by a copy of this one.
+    let simulation = match args.simulate {
+        None => None,
+        Some(SimulationMode::Constant) => Some(SimulatedSensor::constant(initial)),
+        Some(SimulationMode::RandomWalk) => Some(SimulatedSensor::random_walk(initial)),
+        Some(SimulationMode::Script) => {
+            // clap enforces that a script is given in this mode.
+            let path = args.sim_script.as_ref().expect("missing simulation script");
+            Some(SimulatedSensor::load_script(path)?)
+        }
+    };
+
+    let spawn = move |spec: &SensorSpec, config: &Config| match &simulation {
+        Some(sensor) => sampler::spawn(sensor.clone(), config, spec),
+        None => {
+            let (device, address, settings) = (spec.device.clone(), spec.address, spec.settings());
+            let sensor = RecoveringSensor::new(
+                move || Bme280Sensor::open(&device, address, settings),
+                config.recovery,
+                &spec.labels(),
+            );
+            sampler::spawn(sensor, config, spec)
+        }
+    };
+    let active = ActiveConfig::start(config, args.config.clone(), Box::new(spawn));
 
-    loop {
-        let (stream, _) = listener.accept().await?;
+    tokio::spawn(reload_on_hangup(active.clone()));
+    server::serve(listener, TempServer::new(active)).await
+}
 
-        let server = server.clone();
-        tokio::task::spawn(async {
-            if let Err(err) = Http::new().serve_connection(stream, server).await {
-                println!("Failed to serve connection: {:?}", err);
-            }
-        });
+/// Reloads the configuration whenever the process receives SIGHUP.
+async fn reload_on_hangup(active: Arc<ActiveConfig>) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        if !active.can_reload() {
+            println!("Ignoring SIGHUP, as there is no configuration file to reload");
+            continue;
+        }
+        // Failures are logged and reported in metrics by `reload`.
+        let _ = active.reload().await;
     }
+    Ok(())
 }
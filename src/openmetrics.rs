@@ -0,0 +1,208 @@
+//! The OpenMetrics text format, which the `prometheus` crate cannot encode
+//! itself.
+//!
+//! Unlike the older text format it declares the unit of each metric, ends
+//! with `# EOF`, and reports when each counter and histogram started
+//! counting in a `_created` sample.
+
+use prometheus::proto::{LabelPair, Metric, MetricFamily, MetricType};
+use prometheus::{Encoder, Error, Result};
+use std::io::Write;
+use std::time::SystemTime;
+
+use crate::sampler::unix_seconds;
+
+pub const OPENMETRICS_FORMAT: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Units metric names may end with, as the OpenMetrics `UNIT` of the
+/// metric.
+const UNITS: [&str; 8] = [
+    "celsius",
+    "pascals",
+    "kilopascals",
+    "percent",
+    "meters",
+    "seconds",
+    "grams_per_cubic_meter",
+    "grams_per_kilogram",
+];
+
+/// Encodes metric families in the OpenMetrics text format, looking up when
+/// each counter and histogram was created with `created`.
+pub struct OpenMetricsEncoder<F> {
+    created: F,
+}
+
+impl<F: Fn(&Metric) -> Option<SystemTime>> OpenMetricsEncoder<F> {
+    pub fn new(created: F) -> Self {
+        OpenMetricsEncoder { created }
+    }
+
+    fn encode_family(&self, family: &MetricFamily, writer: &mut dyn Write) -> Result<()> {
+        if family.get_metric().is_empty() {
+            return Err(Error::Msg(format!(
+                "metric family {} has no metrics",
+                family.get_name()
+            )));
+        }
+
+        // Samples are named after the family, plus a suffix depending on the
+        // type, which is not part of the family's name.
+        let (name, kind) = match family.get_field_type() {
+            MetricType::COUNTER => (trim(family.get_name(), "_total"), "counter"),
+            MetricType::GAUGE if family.get_name().ends_with("_info") => {
+                (trim(family.get_name(), "_info"), "info")
+            }
+            MetricType::GAUGE => (family.get_name(), "gauge"),
+            MetricType::HISTOGRAM => (family.get_name(), "histogram"),
+            MetricType::SUMMARY => (family.get_name(), "summary"),
+            MetricType::UNTYPED => (family.get_name(), "unknown"),
+        };
+
+        writeln!(writer, "# TYPE {} {}", name, kind)?;
+        if let Some(unit) = UNITS
+            .iter()
+            .find(|unit| name.ends_with(&format!("_{}", unit)))
+        {
+            writeln!(writer, "# UNIT {} {}", name, unit)?;
+        }
+        if !family.get_help().is_empty() {
+            writeln!(writer, "# HELP {} {}", name, escape(family.get_help()))?;
+        }
+
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    let value = metric.get_counter().get_value();
+                    write_sample(writer, name, "_total", labels, None, value)?;
+                    self.write_created(writer, name, metric)?;
+                }
+                MetricType::GAUGE if kind == "info" => {
+                    let value = metric.get_gauge().get_value();
+                    write_sample(writer, name, "_info", labels, None, value)?;
+                }
+                MetricType::GAUGE => {
+                    let value = metric.get_gauge().get_value();
+                    write_sample(writer, name, "", labels, None, value)?;
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let count = histogram.get_sample_count() as f64;
+                    let mut infinite = false;
+                    for bucket in histogram.get_bucket() {
+                        let bound = bucket.get_upper_bound();
+                        infinite |= bound == f64::INFINITY;
+                        let le = Some(("le", format_float(bound)));
+                        let value = bucket.get_cumulative_count() as f64;
+                        write_sample(writer, name, "_bucket", labels, le, value)?;
+                    }
+                    // The last bucket must be infinite.
+                    if !infinite {
+                        let le = Some(("le", "+Inf".to_string()));
+                        write_sample(writer, name, "_bucket", labels, le, count)?;
+                    }
+                    write_sample(writer, name, "_count", labels, None, count)?;
+                    let sum = histogram.get_sample_sum();
+                    write_sample(writer, name, "_sum", labels, None, sum)?;
+                    self.write_created(writer, name, metric)?;
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let label = Some(("quantile", format_float(quantile.get_quantile())));
+                        write_sample(writer, name, "", labels, label, quantile.get_value())?;
+                    }
+                    let count = summary.get_sample_count() as f64;
+                    write_sample(writer, name, "_count", labels, None, count)?;
+                    let sum = summary.get_sample_sum();
+                    write_sample(writer, name, "_sum", labels, None, sum)?;
+                    self.write_created(writer, name, metric)?;
+                }
+                MetricType::UNTYPED => {
+                    let value = metric.get_untyped().get_value();
+                    write_sample(writer, name, "", labels, None, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_created(&self, writer: &mut dyn Write, name: &str, metric: &Metric) -> Result<()> {
+        match (self.created)(metric) {
+            Some(created) => {
+                let created = unix_seconds(created);
+                write_sample(writer, name, "_created", metric.get_label(), None, created)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<F: Fn(&Metric) -> Option<SystemTime>> Encoder for OpenMetricsEncoder<F> {
+    fn encode<W: Write>(&self, families: &[MetricFamily], writer: &mut W) -> Result<()> {
+        for family in families {
+            self.encode_family(family, writer)?;
+        }
+        writeln!(writer, "# EOF")?;
+        Ok(())
+    }
+
+    fn format_type(&self) -> &str {
+        OPENMETRICS_FORMAT
+    }
+}
+
+fn trim<'a>(name: &'a str, suffix: &str) -> &'a str {
+    name.strip_suffix(suffix).unwrap_or(name)
+}
+
+fn write_sample(
+    writer: &mut dyn Write,
+    name: &str,
+    suffix: &str,
+    labels: &[LabelPair],
+    extra: Option<(&str, String)>,
+    value: f64,
+) -> Result<()> {
+    write!(writer, "{}{}", name, suffix)?;
+    let mut labels: Vec<(&str, &str)> = labels
+        .iter()
+        .map(|label| (label.get_name(), label.get_value()))
+        .collect();
+    if let Some((name, value)) = &extra {
+        labels.push((name, value));
+    }
+    if !labels.is_empty() {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+            .collect();
+        write!(writer, "{{{}}}", labels.join(","))?;
+    }
+    writeln!(writer, " {}", format_float(value))?;
+    Ok(())
+}
+
+/// Escapes backslashes, double quotes and newlines, as in label values and
+/// help texts.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// Formats `value` as OpenMetrics expects, with a decimal point so that
+/// e.g. bucket bounds are the same whether they are whole or not.
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{:.1}", value)
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,198 @@
+//! Turning measured readings into the ones exported: correcting them,
+//! rejecting implausible ones and smoothing out noise.
+
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Instant;
+
+use crate::correction::Corrections;
+use crate::sensor::Reading;
+
+/// Names of the quantities of a [`Reading`], as used in labels.
+pub const QUANTITIES: [&str; 3] = ["temperature", "pressure", "humidity"];
+
+/// Plausible values of one quantity. Readings outside them are rejected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limit {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Largest plausible change per minute since the previous accepted
+    /// reading
+    pub max_change_per_minute: Option<f64>,
+}
+
+/// Plausible values of each of the quantities a sensor measures.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub temperature: Limit,
+    pub pressure: Limit,
+    pub humidity: Limit,
+}
+
+impl Limits {
+    /// The limit of each of the [`QUANTITIES`], along with its name.
+    pub fn each(&self) -> [(&'static str, &Limit); 3] {
+        [
+            (QUANTITIES[0], &self.temperature),
+            (QUANTITIES[1], &self.pressure),
+            (QUANTITIES[2], &self.humidity),
+        ]
+    }
+}
+
+/// How to smooth readings. The median is taken first, then the moving
+/// average.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Smoothing {
+    /// Export the median of this many of the latest readings
+    pub median: Option<usize>,
+    /// Weight of the latest reading in an exponential moving average,
+    /// between 0 and 1
+    pub ema: Option<f64>,
+}
+
+/// Why a reading was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// Below the minimum or above the maximum
+    OutOfRange,
+    /// Changed faster than the maximum rate
+    RateOfChange,
+}
+
+impl Reason {
+    pub const ALL: [Reason; 2] = [Reason::OutOfRange, Reason::RateOfChange];
+
+    /// Value of the `reason` label for this reason.
+    pub fn label(self) -> &'static str {
+        match self {
+            Reason::OutOfRange => "out_of_range",
+            Reason::RateOfChange => "rate_of_change",
+        }
+    }
+}
+
+/// A reading which failed the [`Limits`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejection {
+    /// One of the [`QUANTITIES`]
+    pub quantity: &'static str,
+    pub reason: Reason,
+    pub value: f64,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.reason {
+            Reason::OutOfRange => "out of range",
+            Reason::RateOfChange => "changed too fast",
+        };
+        write!(f, "{} of {} {}", self.quantity, self.value, reason)
+    }
+}
+
+/// Processes the readings of one sensor, one after the other.
+#[derive(Debug)]
+pub struct Processor {
+    corrections: Corrections,
+    limits: Limits,
+    smoothing: Smoothing,
+    /// The latest accepted reading before smoothing
+    accepted: Option<(Instant, Reading)>,
+    window: VecDeque<Reading>,
+    average: Option<Reading>,
+}
+
+impl Processor {
+    pub fn new(corrections: Corrections, limits: Limits, smoothing: Smoothing) -> Self {
+        Processor {
+            corrections,
+            limits,
+            smoothing,
+            accepted: None,
+            window: VecDeque::new(),
+            average: None,
+        }
+    }
+
+    /// Corrects `raw`, measured at `taken`, and checks it against the
+    /// limits, returning the smoothed reading unless it was rejected.
+    /// Rejected readings do not affect later ones.
+    pub fn process(&mut self, raw: &Reading, taken: Instant) -> Result<Reading, Rejection> {
+        let reading = self.corrections.apply(raw);
+        self.check(&reading, taken)?;
+        self.accepted = Some((taken, reading));
+
+        let mut smoothed = reading;
+        if let Some(size) = self.smoothing.median {
+            if self.window.len() == size {
+                self.window.pop_front();
+            }
+            self.window.push_back(reading);
+            smoothed = map(|i| median(self.window.iter().map(|reading| get(reading, i))));
+        }
+        if let Some(weight) = self.smoothing.ema {
+            if let Some(average) = &self.average {
+                smoothed = map(|i| weight * get(&smoothed, i) + (1.0 - weight) * get(average, i));
+            }
+            self.average = Some(smoothed);
+        }
+        Ok(smoothed)
+    }
+
+    fn check(&self, reading: &Reading, taken: Instant) -> Result<(), Rejection> {
+        for (i, (quantity, limit)) in self.limits.each().into_iter().enumerate() {
+            let value = get(reading, i);
+            if value.is_nan() {
+                continue;
+            }
+            let reject = |reason| Rejection {
+                quantity,
+                reason,
+                value,
+            };
+            if limit.min.is_some_and(|min| value < min) || limit.max.is_some_and(|max| value > max)
+            {
+                return Err(reject(Reason::OutOfRange));
+            }
+            if let (Some(rate), Some((previous_taken, previous))) =
+                (limit.max_change_per_minute, &self.accepted)
+            {
+                let minutes = taken.duration_since(*previous_taken).as_secs_f64() / 60.0;
+                if (value - get(previous, i)).abs() > rate * minutes {
+                    return Err(reject(Reason::RateOfChange));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `i`th of the [`QUANTITIES`] of `reading`.
+fn get(reading: &Reading, i: usize) -> f64 {
+    [reading.temperature, reading.pressure, reading.humidity][i]
+}
+
+/// A reading made up of `f` of each of the [`QUANTITIES`].
+fn map(f: impl Fn(usize) -> f64) -> Reading {
+    Reading {
+        temperature: f(0),
+        pressure: f(1),
+        humidity: f(2),
+    }
+}
+
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut values: Vec<f64> = values.collect();
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
@@ -0,0 +1,77 @@
+//! Moisture content of the air, calculated from a reading.
+//!
+//! Each quantity has to be turned on in the configuration to be exported.
+
+use serde::Deserialize;
+
+use crate::derived::Magnus;
+use crate::sensor::Reading;
+
+// Specific gas constant of water vapour in J/(kg·K), and the ratio of the
+// molar masses of water and dry air.
+const WATER_VAPOR_GAS_CONSTANT: f64 = 461.5;
+const MOLAR_MASS_RATIO: f64 = 0.622;
+const ZERO_CELSIUS: f64 = 273.15;
+
+/// A psychrometric quantity which can be exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quantity {
+    SaturationVaporPressure,
+    VaporPressure,
+    VaporPressureDeficit,
+    AbsoluteHumidity,
+    MixingRatio,
+    SpecificHumidity,
+}
+
+/// The psychrometric quantities of one reading.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Psychrometrics {
+    /// Pressure of water vapour in saturated air, in Pascals
+    pub saturation_vapor_pressure: f64,
+    /// Partial pressure of the water vapour in the air, in Pascals
+    pub vapor_pressure: f64,
+    /// How much more water vapour the air could hold, in kilopascals
+    pub vapor_pressure_deficit: f64,
+    /// Mass of water vapour per volume of air, in g/m³
+    pub absolute_humidity: f64,
+    /// Mass of water vapour per mass of dry air, in g/kg
+    pub mixing_ratio: f64,
+    /// Mass of water vapour per mass of moist air, in g/kg
+    pub specific_humidity: f64,
+}
+
+impl Psychrometrics {
+    /// Calculates the quantities of `reading`, using `magnus` for the
+    /// saturation vapour pressure. They are NaN if humidity or pressure were
+    /// not measured.
+    pub fn new(magnus: &Magnus, reading: &Reading) -> Self {
+        let saturation = magnus.saturation_vapor_pressure(reading.temperature);
+        let vapor = saturation * reading.humidity / 100.0;
+        let pressure = reading.pressure;
+        Psychrometrics {
+            saturation_vapor_pressure: saturation,
+            vapor_pressure: vapor,
+            vapor_pressure_deficit: (saturation - vapor) / 1000.0,
+            absolute_humidity: vapor
+                / (WATER_VAPOR_GAS_CONSTANT * (reading.temperature + ZERO_CELSIUS))
+                * 1000.0,
+            mixing_ratio: MOLAR_MASS_RATIO * vapor / (pressure - vapor) * 1000.0,
+            specific_humidity: MOLAR_MASS_RATIO * vapor
+                / (pressure - (1.0 - MOLAR_MASS_RATIO) * vapor)
+                * 1000.0,
+        }
+    }
+
+    pub fn get(&self, quantity: Quantity) -> f64 {
+        match quantity {
+            Quantity::SaturationVaporPressure => self.saturation_vapor_pressure,
+            Quantity::VaporPressure => self.vapor_pressure,
+            Quantity::VaporPressureDeficit => self.vapor_pressure_deficit,
+            Quantity::AbsoluteHumidity => self.absolute_humidity,
+            Quantity::MixingRatio => self.mixing_ratio,
+            Quantity::SpecificHumidity => self.specific_humidity,
+        }
+    }
+}
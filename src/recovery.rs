@@ -0,0 +1,166 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounter, IntCounterVec};
+use std::time::{Duration, Instant};
+
+use crate::sensor::{ErrorKind, Reading, Sensor, SensorError, SensorLabels, SENSOR_LABEL_NAMES};
+
+lazy_static! {
+    static ref REINIT_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "bme280_reinitialisations_total",
+        "Number of times the sensor was reopened and initialised again after failing",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+}
+
+/// When to give up on a failing sensor and open it again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecoveryPolicy {
+    /// Consecutive failed measurements after which the sensor is reopened
+    pub failures: u32,
+    /// Time to wait after the first failed attempt to reopen the sensor
+    pub initial_backoff: Duration,
+    /// Upper bound for the wait, which doubles after every failed attempt
+    pub max_backoff: Duration,
+}
+
+enum State<S> {
+    Open {
+        sensor: S,
+        failures: u32,
+    },
+    Closed {
+        retry_at: Instant,
+        backoff: Duration,
+        last_error: Option<SensorError>,
+    },
+}
+
+/// Wraps a sensor which may fail for good, e.g. because it browned out or
+/// was unplugged, so that it is closed and opened again with `open` after
+/// repeated failures.
+///
+/// The sensor is first opened on the first measurement, so a sensor which
+/// is missing at startup is retried like one which went away later.
+pub struct RecoveringSensor<S, F> {
+    open: F,
+    policy: RecoveryPolicy,
+    state: State<S>,
+    opened: bool,
+    name: String,
+    reinits: IntCounter,
+}
+
+impl<S, F> RecoveringSensor<S, F>
+where
+    S: Sensor,
+    F: FnMut() -> Result<S> + Send + 'static,
+{
+    /// Opens the sensor with `open`, counting reinitialisations in metrics
+    /// labelled with `labels`.
+    pub fn new(open: F, policy: RecoveryPolicy, labels: &SensorLabels) -> Self {
+        RecoveringSensor {
+            open,
+            policy,
+            state: State::Closed {
+                retry_at: Instant::now(),
+                backoff: policy.initial_backoff,
+                last_error: None,
+            },
+            opened: false,
+            name: labels.name().to_string(),
+            reinits: REINIT_COUNTER.with_label_values(&labels.values()),
+        }
+    }
+
+    fn reopen(&mut self) -> Result<(), SensorError> {
+        let State::Closed {
+            retry_at,
+            backoff,
+            last_error,
+        } = &mut self.state
+        else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        if now < *retry_at {
+            let last_error = last_error
+                .clone()
+                .unwrap_or_else(|| SensorError::new(ErrorKind::Other, "sensor closed"));
+            return Err(SensorError::new(
+                last_error.kind(),
+                format!(
+                    "{} (retrying in {:.1}s)",
+                    last_error,
+                    (*retry_at - now).as_secs_f64()
+                ),
+            ));
+        }
+
+        match (self.open)() {
+            Ok(sensor) => {
+                if self.opened {
+                    println!("Reinitialised {}", self.name);
+                    self.reinits.inc();
+                }
+                self.opened = true;
+                self.state = State::Open {
+                    sensor,
+                    failures: 0,
+                };
+                Ok(())
+            }
+            Err(err) => {
+                // Failing to open the bus device at all is as much a bus
+                // failure as one during a transaction.
+                let kind = err
+                    .downcast_ref::<SensorError>()
+                    .map_or(ErrorKind::Bus, SensorError::kind);
+                let error = SensorError::new(kind, format!("{:#}", err));
+                println!(
+                    "Failed to open {}, retrying in {:?}: {}",
+                    self.name, backoff, error
+                );
+                *retry_at = now + *backoff;
+                *backoff = (*backoff * 2).min(self.policy.max_backoff);
+                *last_error = Some(error.clone());
+                Err(error)
+            }
+        }
+    }
+}
+
+impl<S, F> Sensor for RecoveringSensor<S, F>
+where
+    S: Sensor,
+    F: FnMut() -> Result<S> + Send + 'static,
+{
+    fn measure(&mut self) -> Result<Reading, SensorError> {
+        self.reopen()?;
+
+        let State::Open { sensor, failures } = &mut self.state else {
+            unreachable!("sensor is open after reopening");
+        };
+        let result = sensor.measure();
+        match result {
+            Ok(_) => *failures = 0,
+            Err(_) => {
+                *failures += 1;
+                if *failures >= self.policy.failures {
+                    println!(
+                        "Closing {} after {} consecutive failed measurements",
+                        self.name, failures
+                    );
+                    self.state = State::Closed {
+                        retry_at: Instant::now(),
+                        backoff: self.policy.initial_backoff,
+                        last_error: None,
+                    };
+                }
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{bail, Result};
+use lazy_static::lazy_static;
+use prometheus::{register_gauge, Gauge};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+
+use crate::config::{Config, SensorSpec};
+use crate::sampler::{unix_seconds, LatestSample, Sampler};
+use crate::server;
+
+lazy_static! {
+    static ref RELOAD_SUCCESS_GAUGE: Gauge = register_gauge!(
+        "bme280_config_last_reload_successful",
+        "Whether the last attempt to reload the configuration succeeded"
+    )
+    .unwrap();
+    static ref RELOAD_TIMESTAMP_GAUGE: Gauge = register_gauge!(
+        "bme280_config_last_reload_success_timestamp_seconds",
+        "Unix time of the last successful configuration reload"
+    )
+    .unwrap();
+}
+
+/// Starts sampling the sensor described by a [`SensorSpec`], using the
+/// sampling settings of a [`Config`].
+pub type SpawnSampler = Box<dyn Fn(&SensorSpec, &Config) -> Sampler + Send + Sync>;
+
+/// A configuration together with the sensors it describes, as one
+/// consistent view for the HTTP server.
+pub struct Active {
+    pub config: Config,
+    pub sensors: Vec<LatestSample>,
+}
+
+/// The configuration in effect, which can be replaced by reloading the file
+/// it came from.
+pub struct ActiveConfig {
+    path: Option<PathBuf>,
+    spawn: SpawnSampler,
+    active: RwLock<Arc<Active>>,
+    // Held for the whole of a reload, so that reloads happen one at a time.
+    samplers: Mutex<Vec<(SensorSpec, Sampler)>>,
+}
+
+impl ActiveConfig {
+    /// Starts sampling the sensors in `config`, which was read from `path`
+    /// if it came from a file.
+    pub fn start(config: Config, path: Option<PathBuf>, spawn: SpawnSampler) -> Arc<Self> {
+        let samplers: Vec<_> = config
+            .sensors
+            .iter()
+            .map(|spec| (spec.clone(), spawn(spec, &config)))
+            .collect();
+        server::set_info(None, &config);
+        let active = Active {
+            sensors: latest_samples(&samplers),
+            config,
+        };
+
+        RELOAD_SUCCESS_GAUGE.set(1.0);
+        RELOAD_TIMESTAMP_GAUGE.set(unix_seconds(SystemTime::now()));
+        Arc::new(ActiveConfig {
+            path,
+            spawn,
+            active: RwLock::new(Arc::new(active)),
+            samplers: Mutex::new(samplers),
+        })
+    }
+
+    /// Whether there is a configuration file to reload, which there is not
+    /// when the exporter was configured on the command line.
+    pub fn can_reload(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn get(&self) -> Arc<Active> {
+        self.active
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Reads the configuration file again and switches over to it, leaving
+    /// the current configuration in place if the file is invalid.
+    ///
+    /// Sensors whose settings did not change keep being sampled without
+    /// interruption; the others are closed and opened again.
+    ///
+    /// Without a configuration file there is nothing to reload, which is
+    /// not reported as a failed reload.
+    pub async fn reload(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            bail!("not started from a configuration file");
+        };
+        let result = self.try_reload(path).await;
+        match &result {
+            Ok(()) => {
+                RELOAD_SUCCESS_GAUGE.set(1.0);
+                RELOAD_TIMESTAMP_GAUGE.set(unix_seconds(SystemTime::now()));
+                println!("Reloaded configuration");
+            }
+            Err(err) => {
+                RELOAD_SUCCESS_GAUGE.set(0.0);
+                println!("Failed to reload configuration: {:#}", err);
+            }
+        }
+        result
+    }
+
+    async fn try_reload(&self, path: &Path) -> Result<()> {
+        let config = Config::load(path)?;
+
+        let mut samplers = self.samplers.lock().await;
+        let previous = self.get();
+        if config.listen_address != previous.config.listen_address {
+            println!("The listen address only changes when restarting");
+        }
+
+        // Samplers can only be kept if they would be started the same way.
+        let sampling_changed = config.sample_interval != previous.config.sample_interval
+            || config.summary_window != previous.config.summary_window
+            || config.recovery != previous.config.recovery;
+        let mut kept = Vec::new();
+        for (spec, sampler) in samplers.drain(..) {
+            if !sampling_changed && config.sensors.contains(&spec) {
+                kept.push((spec, sampler));
+            } else {
+                // Stop first, as the new configuration may open the same
+                // sensor again.
+                sampler.stop().await;
+            }
+        }
+
+        for spec in &config.sensors {
+            let sampler = match kept.iter().position(|(kept, _)| kept == spec) {
+                Some(i) => kept.swap_remove(i).1,
+                None => (self.spawn)(spec, &config),
+            };
+            samplers.push((spec.clone(), sampler));
+        }
+
+        server::set_info(Some(&previous.config), &config);
+        let active = Active {
+            sensors: latest_samples(&samplers),
+            config,
+        };
+        *self.active.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(active);
+        Ok(())
+    }
+}
+
+fn latest_samples(samplers: &[(SensorSpec, Sampler)]) -> Vec<LatestSample> {
+    samplers
+        .iter()
+        .map(|(_, sampler)| sampler.latest().clone())
+        .collect()
+}
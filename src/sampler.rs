@@ -0,0 +1,351 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    register_gauge_vec, register_histogram_vec, register_int_counter_vec, GaugeVec, HistogramVec,
+    IntCounterVec,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::config::{Config, SensorSpec};
+use crate::processing::{Processor, Reason, Rejection, QUANTITIES};
+use crate::sensor::{ErrorKind, Reading, Sensor, SensorError, SensorLabels, SENSOR_LABEL_NAMES};
+use crate::summary::{Recorder, Summary, Window};
+use crate::tendency::{PressureHistory, Tendency};
+
+const ERROR_LABEL_NAMES: [&str; 5] = [
+    SENSOR_LABEL_NAMES[0],
+    SENSOR_LABEL_NAMES[1],
+    SENSOR_LABEL_NAMES[2],
+    SENSOR_LABEL_NAMES[3],
+    "kind",
+];
+
+const REJECTION_LABEL_NAMES: [&str; 6] = [
+    SENSOR_LABEL_NAMES[0],
+    SENSOR_LABEL_NAMES[1],
+    SENSOR_LABEL_NAMES[2],
+    SENSOR_LABEL_NAMES[3],
+    "quantity",
+    "reason",
+];
+
+lazy_static! {
+    static ref LAST_SUCCESS_GAUGE: GaugeVec = register_gauge_vec!(
+        "bme280_last_successful_measurement_timestamp_seconds",
+        "Unix time of the last successful measurement",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref DURATION_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        "bme280_measurement_duration_seconds",
+        "Time taken to measure the sensor, including failed attempts",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref ERRORS_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "bme280_measurement_errors_total",
+        "Number of failed measurements by kind of error",
+        &ERROR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref REJECTIONS_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "bme280_rejected_samples_total",
+        "Number of readings rejected as implausible by quantity and reason",
+        &REJECTION_LABEL_NAMES
+    )
+    .unwrap();
+    /// When each sensor was first sampled, by the values of its labels
+    static ref CREATED: Mutex<HashMap<[String; 4], SystemTime>> = Mutex::default();
+    static ref UPDATES: broadcast::Sender<Arc<SensorLabels>> =
+        broadcast::channel(UPDATES_CAPACITY).0;
+}
+
+/// Samples a subscriber may fall behind by before it misses some.
+const UPDATES_CAPACITY: usize = 64;
+
+/// Notifies of every new sample with the labels of its sensor, from the
+/// moment of subscribing.
+pub fn subscribe() -> broadcast::Receiver<Arc<SensorLabels>> {
+    UPDATES.subscribe()
+}
+
+/// A reading along with the time it was taken.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    /// The reading after correction and smoothing
+    pub reading: Reading,
+    /// The reading as measured
+    pub raw: Reading,
+    /// How the corrected pressure changed up to this sample
+    pub tendency: Tendency,
+    /// Wall clock time of the measurement
+    pub timestamp: SystemTime,
+    /// Monotonic time of the measurement, used to judge staleness
+    pub taken: Instant,
+}
+
+impl Sample {
+    fn new(raw: Reading, reading: Reading, taken: Instant) -> Self {
+        Sample {
+            reading,
+            raw,
+            tendency: Tendency::default(),
+            timestamp: SystemTime::now(),
+            taken,
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        self.taken.elapsed()
+    }
+}
+
+struct State {
+    sample: Option<Sample>,
+    error: Option<String>,
+    recorder: Recorder,
+}
+
+/// The most recent successful sample of one sensor, shared between its
+/// sampler and the HTTP server.
+#[derive(Clone)]
+pub struct LatestSample {
+    labels: Arc<SensorLabels>,
+    state: Arc<RwLock<State>>,
+}
+
+impl LatestSample {
+    fn new(labels: SensorLabels, window: Window) -> Self {
+        LatestSample {
+            labels: Arc::new(labels),
+            state: Arc::new(RwLock::new(State {
+                sample: None,
+                error: None,
+                recorder: Recorder::new(window),
+            })),
+        }
+    }
+
+    /// Labels identifying the sensor in metrics.
+    pub fn labels(&self) -> &SensorLabels {
+        &self.labels
+    }
+
+    pub fn get(&self) -> Option<Sample> {
+        self.read().sample
+    }
+
+    /// The latest sample, unless it is older than `max_staleness`.
+    pub fn fresh(&self, max_staleness: Duration) -> Option<Sample> {
+        self.get().filter(|sample| sample.age() <= max_staleness)
+    }
+
+    /// Describes why the most recent measurement failed, or `None` if it
+    /// succeeded.
+    pub fn error(&self) -> Option<String> {
+        self.read().error.clone()
+    }
+
+    /// Statistics of the samples in the summary window for a scrape at
+    /// `now`. When the window ends with each scrape, this starts the next
+    /// one.
+    pub fn summarize(&self, now: Instant) -> Summary {
+        self.write().recorder.summarize(now)
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, State> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set(&self, sample: Sample) {
+        {
+            let mut state = self.write();
+            state.recorder.push(sample.taken, sample.reading);
+            state.sample = Some(sample);
+            state.error = None;
+        }
+        // Sending fails only when nobody is subscribed.
+        let _ = UPDATES.send(self.labels.clone());
+    }
+
+    fn set_error(&self, error: String) {
+        self.write().error = Some(error);
+    }
+}
+
+/// A background task measuring a sensor, see [`spawn`].
+pub struct Sampler {
+    latest: LatestSample,
+    stop: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl Sampler {
+    /// The handle through which the latest sample can be read.
+    pub fn latest(&self) -> &LatestSample {
+        &self.latest
+    }
+
+    /// Stops measuring, returning once the sensor has been closed.
+    pub async fn stop(self) {
+        self.stop.notify_one();
+        // A measurement in progress is finished first, so that the sensor is
+        // no longer in use once this returns.
+        let _ = self.task.await;
+    }
+}
+
+/// Measures `sensor`, as described by `spec`, every sample interval of
+/// `config` on a background task, starting immediately, until the returned
+/// sampler is stopped.
+///
+/// Readings are corrected, checked and smoothed as the spec says. Failed
+/// measurements and rejected readings are logged and leave the previous
+/// sample in place.
+pub fn spawn<S: Sensor>(sensor: S, config: &Config, spec: &SensorSpec) -> Sampler {
+    let labels = spec.labels();
+    CREATED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(labels.values().map(str::to_string))
+        .or_insert_with(SystemTime::now);
+    // Export every kind of error from the start, rather than only once it
+    // first happens.
+    for kind in ErrorKind::ALL {
+        ERRORS_COUNTER.with_label_values(&labels.values_with(kind.label()));
+    }
+    for quantity in QUANTITIES {
+        for reason in Reason::ALL {
+            REJECTIONS_COUNTER.with_label_values(&rejection_labels(&labels, quantity, reason));
+        }
+    }
+
+    let latest = LatestSample::new(labels, config.summary_window);
+    let stop = Arc::new(Notify::new());
+    let task = tokio::spawn(run(
+        Arc::new(Mutex::new(sensor)),
+        config.sample_interval,
+        Processor::new(spec.correction, spec.limits, spec.smoothing),
+        latest.clone(),
+        stop.clone(),
+    ));
+    Sampler { latest, stop, task }
+}
+
+async fn run<S: Sensor>(
+    sensor: Arc<Mutex<S>>,
+    interval: Duration,
+    mut processor: Processor,
+    latest: LatestSample,
+    stop: Arc<Notify>,
+) {
+    let labels = latest.labels().values();
+    let duration = DURATION_HISTOGRAM.with_label_values(&labels);
+    let last_success = LAST_SUCCESS_GAUGE.with_label_values(&labels);
+
+    let mut history = PressureHistory::new();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = stop.notified() => return,
+        }
+
+        // Measuring blocks for the duration of the conversion, so keep it off
+        // the async worker threads.
+        let sensor = sensor.clone();
+        let timer = duration.start_timer();
+        let result = tokio::task::spawn_blocking(move || measure(&sensor)).await;
+        timer.observe_duration();
+
+        let result = result.unwrap_or_else(|err| {
+            // The panic has also poisoned the lock, which the next
+            // measurement will report.
+            Err(SensorError::new(
+                ErrorKind::Other,
+                format!("measurement panicked: {}", err),
+            ))
+        });
+
+        match result {
+            Ok(raw) => {
+                let taken = Instant::now();
+                match processor.process(&raw, taken) {
+                    Ok(reading) => {
+                        let mut sample = Sample::new(raw, reading, taken);
+                        history.push(taken, sample.reading.pressure);
+                        sample.tendency = history.tendency();
+                        last_success.set(unix_seconds(sample.timestamp));
+                        latest.set(sample);
+                    }
+                    Err(rejection) => reject(&latest, &rejection),
+                }
+            }
+            Err(err) => {
+                println!("Failed to measure {}: {}", latest.labels().name(), err);
+                ERRORS_COUNTER
+                    .with_label_values(&latest.labels().values_with(err.kind().label()))
+                    .inc();
+                latest.set_error(err.to_string());
+            }
+        }
+    }
+}
+
+/// When the counters and histograms of the sensor with the label values
+/// `labels` were created, which is when it was first sampled. They are kept
+/// when it is sampled anew after a reload.
+pub fn created(labels: &[&str; 4]) -> Option<SystemTime> {
+    CREATED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&labels.map(str::to_string))
+        .copied()
+}
+
+fn reject(latest: &LatestSample, rejection: &Rejection) {
+    println!(
+        "Rejected reading of {}: {}",
+        latest.labels().name(),
+        rejection
+    );
+    REJECTIONS_COUNTER
+        .with_label_values(&rejection_labels(
+            latest.labels(),
+            rejection.quantity,
+            rejection.reason,
+        ))
+        .inc();
+}
+
+fn rejection_labels<'a>(
+    labels: &'a SensorLabels,
+    quantity: &'a str,
+    reason: Reason,
+) -> [&'a str; 6] {
+    let [name, bus, address, location] = labels.values();
+    [name, bus, address, location, quantity, reason.label()]
+}
+
+fn measure<S: Sensor>(sensor: &Mutex<S>) -> Result<Reading, SensorError> {
+    sensor
+        .lock()
+        .map_err(|e| SensorError::new(ErrorKind::LockPoisoned, format!("lock poisoned: {:?}", e)))?
+        .measure()
+}
+
+pub(crate) fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or(0.0)
+}
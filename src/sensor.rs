@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use embedded_hal::i2c::blocking::I2c;
+use linux_embedded_hal::I2cdev;
+use std::fmt;
+
+use crate::cli::SensorAddress;
+use crate::driver::{self, Bme280, Settings};
+
+/// A single sample of the three quantities the BME280 measures.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reading {
+    /// Temperature in degrees Celsius
+    pub temperature: f64,
+    /// Pressure in Pascals
+    pub pressure: f64,
+    /// Relative humidity in percent
+    pub humidity: f64,
+}
+
+/// Names of the labels which tell the metrics of different sensors apart.
+pub const SENSOR_LABEL_NAMES: [&str; 4] = ["sensor", "bus", "address", "location"];
+
+/// Values of the [`SENSOR_LABEL_NAMES`] for one sensor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SensorLabels {
+    values: [String; 4],
+}
+
+impl SensorLabels {
+    pub fn new(name: &str, bus: &str, address: u8, location: &str) -> Self {
+        SensorLabels {
+            values: [
+                name.to_string(),
+                bus.to_string(),
+                format!("{:#04x}", address),
+                location.to_string(),
+            ],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.values[0]
+    }
+
+    /// The label values, in the order of [`SENSOR_LABEL_NAMES`].
+    pub fn values(&self) -> [&str; 4] {
+        [
+            &self.values[0],
+            &self.values[1],
+            &self.values[2],
+            &self.values[3],
+        ]
+    }
+
+    /// The label values followed by `extra`, for metrics with further labels.
+    pub fn values_with<'a>(&'a self, extra: &'a str) -> [&'a str; 5] {
+        let [name, bus, address, location] = self.values();
+        [name, bus, address, location, extra]
+    }
+}
+
+/// Broad classes of measurement failure, for reporting in metrics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The sensor did not acknowledge its address or data; it is likely
+    /// missing or unpowered
+    Nack,
+    /// Any other I2C bus failure
+    Bus,
+    /// The chip ID is neither that of a BME280 nor of a BMP280
+    UnsupportedChip,
+    /// The raw data could not be turned into a reading
+    Compensation,
+    /// A previous measurement panicked while holding the sensor
+    LockPoisoned,
+    /// Anything else, such as a measurement which never finishes or a
+    /// simulated failure
+    Other,
+}
+
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 6] = [
+        ErrorKind::Nack,
+        ErrorKind::Bus,
+        ErrorKind::UnsupportedChip,
+        ErrorKind::Compensation,
+        ErrorKind::LockPoisoned,
+        ErrorKind::Other,
+    ];
+
+    /// Value of the `kind` label for this kind of error.
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorKind::Nack => "nack",
+            ErrorKind::Bus => "bus",
+            ErrorKind::UnsupportedChip => "unsupported_chip",
+            ErrorKind::Compensation => "compensation",
+            ErrorKind::LockPoisoned => "lock_poisoned",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+/// A failed measurement.
+#[derive(Clone, Debug)]
+pub struct SensorError {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl SensorError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        SensorError {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for SensorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for SensorError {}
+
+impl<E: embedded_hal::i2c::Error> From<driver::Error<E>> for SensorError {
+    fn from(error: driver::Error<E>) -> Self {
+        let kind = match &error {
+            driver::Error::Bus(bus) => match bus.kind() {
+                embedded_hal::i2c::ErrorKind::NoAcknowledge(_) => ErrorKind::Nack,
+                _ => ErrorKind::Bus,
+            },
+            driver::Error::UnsupportedChip(_) => ErrorKind::UnsupportedChip,
+            driver::Error::Compensation => ErrorKind::Compensation,
+            driver::Error::Timeout
+            | driver::Error::NotMeasuring
+            | driver::Error::SettingsLost
+            | driver::Error::NotMeasured => ErrorKind::Other,
+        };
+        SensorError::new(kind, format!("{:?}", error))
+    }
+}
+
+/// A source of temperature, pressure, and humidity readings.
+pub trait Sensor: Send + 'static {
+    fn measure(&mut self) -> Result<Reading, SensorError>;
+}
+
+/// A BME280 attached to an I2C bus.
+pub struct Bme280Sensor<I2C> {
+    bme280: Bme280<I2C>,
+}
+
+impl Bme280Sensor<I2cdev> {
+    /// Opens the I2C bus at `device` and initialises the sensor at `address`
+    /// with `settings`.
+    pub fn open(device: &str, address: SensorAddress, settings: Settings) -> Result<Self> {
+        let i2c_bus = I2cdev::new(device)?;
+        Self::with_settings(i2c_bus, address, settings)
+    }
+}
+
+impl<I2C: I2c> Bme280Sensor<I2C> {
+    /// Initialises the sensor at `address` with the default settings.
+    pub fn new(i2c_bus: I2C, address: SensorAddress) -> Result<Self> {
+        Self::with_settings(i2c_bus, address, Settings::default())
+    }
+
+    pub fn with_settings(i2c_bus: I2C, address: SensorAddress, settings: Settings) -> Result<Self> {
+        let mut bme280 = Bme280::new(i2c_bus, address.value());
+        bme280
+            .init(settings)
+            .map_err(SensorError::from)
+            .context("unable to init")?;
+        Ok(Bme280Sensor { bme280 })
+    }
+}
+
+impl<I2C: I2c + Send + 'static> Sensor for Bme280Sensor<I2C> {
+    fn measure(&mut self) -> Result<Reading, SensorError> {
+        Ok(self.bme280.measure()?)
+    }
+}
@@ -0,0 +1,967 @@
+use anyhow::Result;
+use hyper::body::HttpBody;
+use hyper::header::{
+    HeaderName, ACCEPT, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY,
+};
+use hyper::server::conn::Http;
+use hyper::service::Service;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use lazy_static::lazy_static;
+use prometheus::proto::{LabelPair, Metric, MetricFamily};
+use prometheus::{
+    register_gauge_vec, Encoder, GaugeVec, ProtobufEncoder, TextEncoder, PROTOBUF_FORMAT,
+};
+use tokio::net::TcpListener;
+
+use crate::api;
+use crate::cli::FailureResponse;
+use crate::compression::Encoding;
+use crate::config::{Config, Exporter, SensorSpec};
+use crate::derived;
+use crate::openmetrics::{OpenMetricsEncoder, OPENMETRICS_FORMAT};
+use crate::psychrometrics::{Psychrometrics, Quantity};
+use crate::reload::{Active, ActiveConfig};
+use crate::sampler::{self, LatestSample, Sample};
+use crate::sensor::SENSOR_LABEL_NAMES;
+use crate::stream;
+use crate::summary::{Statistics, Summary};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+lazy_static! {
+    static ref TEMPERATURE_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_temperature_celsius",
+        "Ambient temperature in Celsius",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref PRESSURE_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_pressure_pascals",
+        "Atmospheric pressure in Pascals",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref HUMIDITY_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_humidity_percent",
+        "Relative humidity in %",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref DEW_POINT_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_dew_point_celsius",
+        "Dew point in Celsius, calculated from temperature and relative humidity",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SEA_LEVEL_PRESSURE_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_sea_level_pressure_pascals",
+        "Atmospheric pressure in Pascals reduced to sea level from the configured altitude",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref ALTITUDE_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_altitude_meters",
+        "Altitude in meters estimated from the configured sea level pressure",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref PRESSURE_CHANGE_1H_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_pressure_change_1h_pascals",
+        "Change of the atmospheric pressure over the last hour in Pascals",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref PRESSURE_CHANGE_3H_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_pressure_change_3h_pascals",
+        "Change of the atmospheric pressure over the last three hours in Pascals",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref PRESSURE_TENDENCY_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_pressure_tendency_code",
+        "Characteristic of the pressure change over the last three hours, by WMO code table 0200",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref HEAT_INDEX_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_heat_index_celsius",
+        "Apparent temperature in Celsius by the NWS heat index, from 26.7 Celsius upwards",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref HUMIDEX_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_humidex",
+        "Apparent temperature by the Canadian humidex, from 20 Celsius upwards",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref WET_BULB_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_wet_bulb_temperature_celsius",
+        "Wet-bulb temperature in Celsius by Stull's approximation, from -20 to 50 Celsius and 5 to 99% humidity",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SATURATION_VAPOR_PRESSURE_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_saturation_vapor_pressure_pascals",
+        "Pressure of water vapor in saturated air at the ambient temperature in Pascals",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref VAPOR_PRESSURE_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_vapor_pressure_pascals",
+        "Partial pressure of water vapor in the air in Pascals",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref VAPOR_PRESSURE_DEFICIT_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_vapor_pressure_deficit_kilopascals",
+        "Difference between the saturation and actual vapor pressure in kilopascals",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref ABSOLUTE_HUMIDITY_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_absolute_humidity_grams_per_cubic_meter",
+        "Mass of water vapor per volume of air in g/m³",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref MIXING_RATIO_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_mixing_ratio_grams_per_kilogram",
+        "Mass of water vapor per mass of dry air in g/kg",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SPECIFIC_HUMIDITY_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_specific_humidity_grams_per_kilogram",
+        "Mass of water vapor per mass of moist air in g/kg",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref RAW_TEMPERATURE_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_raw_temperature_celsius",
+        "Ambient temperature in Celsius as measured, before correction",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref RAW_PRESSURE_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_raw_pressure_pascals",
+        "Atmospheric pressure in Pascals as measured, before correction",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref RAW_HUMIDITY_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_raw_humidity_percent",
+        "Relative humidity in % as measured, before correction",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SUMMARY_TEMPERATURE_MIN_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_summary_temperature_min_celsius",
+        "Minimum ambient temperature in Celsius over the summary window",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SUMMARY_TEMPERATURE_MAX_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_summary_temperature_max_celsius",
+        "Maximum ambient temperature in Celsius over the summary window",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SUMMARY_TEMPERATURE_MEAN_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_summary_temperature_mean_celsius",
+        "Mean ambient temperature in Celsius over the summary window",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SUMMARY_PRESSURE_MIN_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_summary_pressure_min_pascals",
+        "Minimum atmospheric pressure in Pascals over the summary window",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SUMMARY_PRESSURE_MAX_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_summary_pressure_max_pascals",
+        "Maximum atmospheric pressure in Pascals over the summary window",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SUMMARY_PRESSURE_MEAN_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_summary_pressure_mean_pascals",
+        "Mean atmospheric pressure in Pascals over the summary window",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SUMMARY_HUMIDITY_MIN_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_summary_humidity_min_percent",
+        "Minimum relative humidity in % over the summary window",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SUMMARY_HUMIDITY_MAX_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_summary_humidity_max_percent",
+        "Maximum relative humidity in % over the summary window",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SUMMARY_HUMIDITY_MEAN_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_summary_humidity_mean_percent",
+        "Mean relative humidity in % over the summary window",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SUMMARY_SAMPLES_GAUGE: GaugeVec = register_gauge_vec!(
+        "meter_summary_samples",
+        "Number of readings of each quantity over the summary window",
+        &SUMMARY_LABEL_NAMES
+    )
+    .unwrap();
+    static ref UP_GAUGE: GaugeVec = register_gauge_vec!(
+        "bme280_up",
+        "Whether the last measurement of the sensor succeeded and is recent enough to export",
+        &SENSOR_LABEL_NAMES
+    )
+    .unwrap();
+    static ref SETTINGS_GAUGE: GaugeVec = register_gauge_vec!(
+        "bme280_settings_info",
+        "Mode, oversampling, IIR filter and standby settings the sensor is configured with",
+        &SETTINGS_LABEL_NAMES
+    )
+    .unwrap();
+    static ref CORRECTION_GAUGE: GaugeVec = register_gauge_vec!(
+        "bme280_correction_info",
+        "Gain and offset applied to each quantity the sensor measures",
+        &CORRECTION_LABEL_NAMES
+    )
+    .unwrap();
+}
+
+/// Names of the labels which metrics have besides those identifying the
+/// sensor, which constant labels must not repeat.
+pub const METRIC_LABEL_NAMES: [&str; 12] = [
+    "kind",
+    "quantity",
+    "reason",
+    "mode",
+    "temperature_oversampling",
+    "pressure_oversampling",
+    "humidity_oversampling",
+    "filter",
+    "standby",
+    "gain",
+    "offset",
+    // The bucket of a histogram
+    "le",
+];
+
+const SETTINGS_LABEL_NAMES: [&str; 10] = [
+    SENSOR_LABEL_NAMES[0],
+    SENSOR_LABEL_NAMES[1],
+    SENSOR_LABEL_NAMES[2],
+    SENSOR_LABEL_NAMES[3],
+    "mode",
+    "temperature_oversampling",
+    "pressure_oversampling",
+    "humidity_oversampling",
+    "filter",
+    "standby",
+];
+
+const SUMMARY_LABEL_NAMES: [&str; 5] = [
+    SENSOR_LABEL_NAMES[0],
+    SENSOR_LABEL_NAMES[1],
+    SENSOR_LABEL_NAMES[2],
+    SENSOR_LABEL_NAMES[3],
+    "quantity",
+];
+
+const CORRECTION_LABEL_NAMES: [&str; 7] = [
+    SENSOR_LABEL_NAMES[0],
+    SENSOR_LABEL_NAMES[1],
+    SENSOR_LABEL_NAMES[2],
+    SENSOR_LABEL_NAMES[3],
+    "quantity",
+    "gain",
+    "offset",
+];
+
+/// The group a metric belongs to, given the name it was registered with.
+pub(crate) fn exporter(name: &str) -> Exporter {
+    if name.starts_with("meter_raw_") {
+        Exporter::Raw
+    } else if name.starts_with("meter_summary_") {
+        Exporter::Summary
+    } else if name.starts_with("meter_") {
+        Exporter::Readings
+    } else {
+        Exporter::Health
+    }
+}
+
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// A format `/metrics` can be served in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// The Prometheus text format 0.0.4
+    Text,
+    /// OpenMetrics text 1.0.0
+    OpenMetrics,
+    /// Length-delimited `MetricFamily` protocol buffers
+    Protobuf,
+}
+
+impl Format {
+    /// The format the client prefers by its `Accept` header, or the text
+    /// format if it accepts none of the others.
+    fn negotiate(accept: Option<&str>) -> Format {
+        negotiate(accept, |preference| match preference.value.as_str() {
+            "application/openmetrics-text" => Some(Format::OpenMetrics),
+            "text/plain" => Some(Format::Text),
+            // Other encodings of protocol buffers are not supported.
+            "application/vnd.google.protobuf"
+                if preference.param("proto") == Some("io.prometheus.client.MetricFamily")
+                    && preference.param("encoding") == Some("delimited") =>
+            {
+                Some(Format::Protobuf)
+            }
+            _ => None,
+        })
+        .unwrap_or(Format::Text)
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Text => TEXT_FORMAT,
+            Format::OpenMetrics => OPENMETRICS_FORMAT,
+            Format::Protobuf => PROTOBUF_FORMAT,
+        }
+    }
+
+    fn encode(self, metric_families: &[MetricFamily]) -> prometheus::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        match self {
+            Format::Text => TextEncoder::new().encode(metric_families, &mut buffer)?,
+            Format::OpenMetrics => {
+                OpenMetricsEncoder::new(created).encode(metric_families, &mut buffer)?
+            }
+            Format::Protobuf => ProtobufEncoder::new().encode(metric_families, &mut buffer)?,
+        }
+        Ok(buffer)
+    }
+}
+
+/// One of the comma-separated values of an `Accept` or `Accept-Encoding`
+/// header, e.g. `text/plain;version=0.0.4;q=0.5`.
+struct Preference<'a> {
+    /// The media type or encoding in lower case
+    value: String,
+    params: Vec<(String, &'a str)>,
+    /// From 0, not acceptable at all, to 1
+    quality: f64,
+}
+
+impl<'a> Preference<'a> {
+    fn parse(value: &'a str) -> Self {
+        let mut params = value.split(';').map(str::trim);
+        let value = params.next().unwrap_or_default().to_lowercase();
+        let params: Vec<(String, &str)> = params
+            .filter_map(|param| param.split_once('='))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim()))
+            .collect();
+        let mut preference = Preference {
+            value,
+            params,
+            quality: 1.0,
+        };
+        if let Some(quality) = preference.param("q") {
+            preference.quality = quality.parse().unwrap_or(0.0);
+        }
+        preference
+    }
+
+    fn param(&self, name: &str) -> Option<&'a str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// The most preferred of the values in `header` which `choose` supports.
+/// Of equally preferred values, the first listed wins.
+fn negotiate<T>(header: Option<&str>, choose: impl Fn(&Preference) -> Option<T>) -> Option<T> {
+    let mut best = None;
+    let mut best_quality = 0.0;
+    for preference in header.unwrap_or_default().split(',').map(Preference::parse) {
+        if preference.quality > best_quality {
+            if let Some(value) = choose(&preference) {
+                best = Some(value);
+                best_quality = preference.quality;
+            }
+        }
+    }
+    best
+}
+
+/// The encoding to compress the response to a request accepting the
+/// encodings in `accept` with, if any.
+fn negotiate_encoding(accept: Option<&str>) -> Option<Encoding> {
+    let listed: Vec<Encoding> = accept
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|value| Encoding::from_name(&Preference::parse(value).value))
+        .collect();
+    negotiate(accept, |preference| match preference.value.as_str() {
+        // Any encoding not listed on its own, which would give its own
+        // quality, e.g. 0 to refuse it.
+        "*" => Encoding::ALL
+            .into_iter()
+            .find(|encoding| !listed.contains(encoding)),
+        name => Encoding::from_name(name),
+    })
+}
+
+/// The value of the header `name` of `req`, if it is valid text.
+fn header(req: &Request<Body>, name: HeaderName) -> Option<&str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Compresses the body of `response` with `encoding`, if there is one and
+/// the body is at least `min_size` bytes long. Bodies which are streamed are
+/// left alone.
+async fn compress(
+    response: Response<Body>,
+    encoding: Option<Encoding>,
+    min_size: usize,
+) -> Response<Body> {
+    let Some(size) = response.body().size_hint().exact() else {
+        return response;
+    };
+    let (mut parts, body) = response.into_parts();
+    // Caches must tell apart responses to clients accepting different
+    // encodings.
+    let vary = match parts.headers.get(VARY).and_then(|vary| vary.to_str().ok()) {
+        Some(vary) => format!("{}, Accept-Encoding", vary),
+        None => "Accept-Encoding".to_string(),
+    };
+    parts.headers.insert(VARY, vary.parse().unwrap());
+
+    let encoding = match encoding {
+        Some(encoding) if size >= min_size as u64 => encoding,
+        _ => return Response::from_parts(parts, body),
+    };
+    // A body of known size is already in memory, so reading it cannot fail.
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    match encoding.compress(&body) {
+        Ok(compressed) => {
+            parts
+                .headers
+                .insert(CONTENT_ENCODING, encoding.name().parse().unwrap());
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(compressed))
+        }
+        Err(err) => {
+            println!("Failed to compress response: {}", err);
+            Response::from_parts(parts, Body::from(body))
+        }
+    }
+}
+
+/// When the counter or histogram `metric` was created, if it belongs to a
+/// sensor.
+fn created(metric: &Metric) -> Option<std::time::SystemTime> {
+    sampler::created(&sensor_labels(metric.get_label())?)
+}
+
+#[derive(Clone)]
+pub struct TempServer {
+    active: Arc<ActiveConfig>,
+}
+
+impl TempServer {
+    /// Serves the readings of each sensor in `active` until they are older
+    /// than the configured maximum staleness. Scrapes while none of them has
+    /// a recent reading are answered as the configured `on_failure` says.
+    pub fn new(active: Arc<ActiveConfig>) -> TempServer {
+        TempServer { active }
+    }
+
+    fn metrics(&self, format: Format) -> Response<Body> {
+        let active = self.active.get();
+        let config = &active.config;
+
+        let mut any_fresh = false;
+        for (spec, latest) in config.sensors.iter().zip(&active.sensors) {
+            let labels = latest.labels().values();
+            match latest.fresh(config.max_staleness) {
+                Some(sample) => {
+                    any_fresh = true;
+                    let values = sample_values(config, spec, &sample);
+                    for (gauge, value) in sample_gauges().into_iter().zip(values) {
+                        set_or_remove(gauge, &labels, value);
+                    }
+                    // The reading is still exported until it goes stale, but
+                    // a failing sensor is not up.
+                    let up = latest.error().is_none();
+                    UP_GAUGE
+                        .with_label_values(&labels)
+                        .set(if up { 1.0 } else { 0.0 });
+                }
+                None => {
+                    // Better to report nothing than a value which no longer
+                    // reflects reality. The gauges may not have been set
+                    // yet, so there may be nothing to remove.
+                    for gauge in sample_gauges() {
+                        let _ = gauge.remove_label_values(&labels);
+                    }
+                    UP_GAUGE.with_label_values(&labels).set(0.0);
+                }
+            }
+        }
+
+        if !any_fresh && config.on_failure == FailureResponse::Unavailable {
+            return text_response(StatusCode::SERVICE_UNAVAILABLE, unavailable_reason(&active));
+        }
+
+        // Only once the scrape is answered with metrics, as summarizing may
+        // start a new window.
+        if config.exporters.contains(&Exporter::Summary) {
+            for latest in &active.sensors {
+                set_summary(latest, &latest.summarize(Instant::now()));
+            }
+        }
+
+        let metric_families = gather(&active);
+        match format.encode(&metric_families) {
+            Ok(buffer) => Response::builder()
+                .header(CONTENT_TYPE, format.content_type())
+                .header(VARY, "Accept")
+                .body(Body::from(buffer))
+                .unwrap(),
+            Err(err) => {
+                println!("Failed to encode metrics: {:?}", err);
+                text_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("unable to encode metrics: {}", err),
+                )
+            }
+        }
+    }
+
+    fn readings(&self) -> Response<Body> {
+        let readings = api::readings(&self.active.get());
+        match serde_json::to_vec(&readings) {
+            Ok(body) => Response::builder()
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+            Err(err) => text_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("unable to encode readings: {}", err),
+            ),
+        }
+    }
+
+    /// Answers `req`, before compression.
+    fn route(
+        &mut self,
+        req: Request<Body>,
+    ) -> Pin<Box<dyn Future<Output = Result<Response<Body>>> + Send>> {
+        match (req.method(), req.uri().path()) {
+            (&Method::GET, "/metrics") => {
+                let response = self.metrics(Format::negotiate(header(&req, ACCEPT)));
+                Box::pin(async { Ok(response) })
+            }
+            (&Method::GET, "/api/v1/readings") => {
+                let response = self.readings();
+                Box::pin(async { Ok(response) })
+            }
+            (&Method::GET, "/api/v1/stream") => {
+                let response = stream::stream(self.active.clone());
+                Box::pin(async { Ok(response) })
+            }
+            (_, "/api/v1/readings" | "/api/v1/stream") => Box::pin(async {
+                Ok(Response::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .header("Allow", "GET")
+                    .body(Body::empty())
+                    .unwrap())
+            }),
+            (&Method::POST, "/-/reload") => self.reload(),
+            (_, "/-/reload") => Box::pin(async {
+                Ok(Response::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .header("Allow", "POST")
+                    .body(Body::empty())
+                    .unwrap())
+            }),
+            _ => Box::pin(async {
+                Ok(Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty())
+                    .unwrap())
+            }),
+        }
+    }
+
+    fn reload(&self) -> Pin<Box<dyn Future<Output = Result<Response<Body>>> + Send>> {
+        let active = self.active.clone();
+        Box::pin(async move {
+            if !active.can_reload() {
+                return Ok(text_response(
+                    StatusCode::BAD_REQUEST,
+                    "not started from a configuration file".to_string(),
+                ));
+            }
+            Ok(match active.reload().await {
+                Ok(()) => text_response(StatusCode::OK, "configuration reloaded".to_string()),
+                Err(err) => text_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("failed to reload configuration: {:#}", err),
+                ),
+            })
+        })
+    }
+}
+
+/// The gauges exported for the latest sample of each sensor.
+pub(crate) fn sample_gauges() -> [&'static GaugeVec; 21] {
+    [
+        &TEMPERATURE_GAUGE,
+        &PRESSURE_GAUGE,
+        &HUMIDITY_GAUGE,
+        &DEW_POINT_GAUGE,
+        &SEA_LEVEL_PRESSURE_GAUGE,
+        &ALTITUDE_GAUGE,
+        &PRESSURE_CHANGE_1H_GAUGE,
+        &PRESSURE_CHANGE_3H_GAUGE,
+        &PRESSURE_TENDENCY_GAUGE,
+        &HEAT_INDEX_GAUGE,
+        &HUMIDEX_GAUGE,
+        &WET_BULB_GAUGE,
+        &SATURATION_VAPOR_PRESSURE_GAUGE,
+        &VAPOR_PRESSURE_GAUGE,
+        &VAPOR_PRESSURE_DEFICIT_GAUGE,
+        &ABSOLUTE_HUMIDITY_GAUGE,
+        &MIXING_RATIO_GAUGE,
+        &SPECIFIC_HUMIDITY_GAUGE,
+        &RAW_TEMPERATURE_GAUGE,
+        &RAW_PRESSURE_GAUGE,
+        &RAW_HUMIDITY_GAUGE,
+    ]
+}
+
+/// Values of the [`sample_gauges`] for a sample of the sensor described by
+/// `spec`, NaN for those which are not known.
+pub(crate) fn sample_values(config: &Config, spec: &SensorSpec, sample: &Sample) -> [f64; 21] {
+    let (reading, raw, tendency) = (&sample.reading, &sample.raw, &sample.tendency);
+    let sea_level_pressure = spec.altitude.map_or(f64::NAN, |altitude| {
+        derived::sea_level_pressure(reading.pressure, reading.temperature, altitude)
+    });
+    let altitude = spec
+        .sea_level_pressure
+        .map_or(f64::NAN, |sea_level_pressure| {
+            derived::altitude(reading.pressure, reading.temperature, sea_level_pressure)
+        });
+    let psychrometrics = Psychrometrics::new(&config.magnus, reading);
+    let psychrometric = |quantity| {
+        if config.psychrometrics.contains(&quantity) {
+            psychrometrics.get(quantity)
+        } else {
+            f64::NAN
+        }
+    };
+    [
+        reading.temperature,
+        reading.pressure,
+        reading.humidity,
+        config
+            .magnus
+            .dew_point(reading.temperature, reading.humidity),
+        sea_level_pressure,
+        altitude,
+        tendency.change_1h.unwrap_or(f64::NAN),
+        tendency.change_3h.unwrap_or(f64::NAN),
+        tendency.code.map_or(f64::NAN, f64::from),
+        derived::heat_index(reading.temperature, reading.humidity),
+        derived::humidex(&config.magnus, reading.temperature, reading.humidity),
+        derived::wet_bulb_temperature(reading.temperature, reading.humidity),
+        psychrometric(Quantity::SaturationVaporPressure),
+        psychrometric(Quantity::VaporPressure),
+        psychrometric(Quantity::VaporPressureDeficit),
+        psychrometric(Quantity::AbsoluteHumidity),
+        psychrometric(Quantity::MixingRatio),
+        psychrometric(Quantity::SpecificHumidity),
+        raw.temperature,
+        raw.pressure,
+        raw.humidity,
+    ]
+}
+
+/// Sets the summary gauges of the sensor of `latest` to `summary`.
+fn set_summary(latest: &LatestSample, summary: &Summary) {
+    let labels = latest.labels().values();
+    let gauges: [(&str, &Statistics, [&GaugeVec; 3]); 3] = [
+        (
+            "temperature",
+            &summary.temperature,
+            [
+                &SUMMARY_TEMPERATURE_MIN_GAUGE,
+                &SUMMARY_TEMPERATURE_MAX_GAUGE,
+                &SUMMARY_TEMPERATURE_MEAN_GAUGE,
+            ],
+        ),
+        (
+            "pressure",
+            &summary.pressure,
+            [
+                &SUMMARY_PRESSURE_MIN_GAUGE,
+                &SUMMARY_PRESSURE_MAX_GAUGE,
+                &SUMMARY_PRESSURE_MEAN_GAUGE,
+            ],
+        ),
+        (
+            "humidity",
+            &summary.humidity,
+            [
+                &SUMMARY_HUMIDITY_MIN_GAUGE,
+                &SUMMARY_HUMIDITY_MAX_GAUGE,
+                &SUMMARY_HUMIDITY_MEAN_GAUGE,
+            ],
+        ),
+    ];
+    for (quantity, statistics, [min, max, mean]) in gauges {
+        // Without readings in the window the statistics are NaN, and only
+        // the count of zero is exported.
+        set_or_remove(min, &labels, statistics.min);
+        set_or_remove(max, &labels, statistics.max);
+        set_or_remove(mean, &labels, statistics.mean());
+        SUMMARY_SAMPLES_GAUGE
+            .with_label_values(&latest.labels().values_with(quantity))
+            .set(statistics.count as f64);
+    }
+}
+
+/// Sets the info metrics describing how each sensor in `config` is set up,
+/// removing those of sensors in the `previous` configuration which are gone
+/// or set up differently now.
+///
+/// Scrapes never see the metrics of the current sensors missing, unlike if
+/// they were reset and set again.
+pub(crate) fn set_info(previous: Option<&Config>, config: &Config) {
+    let (settings, corrections) = info_labels(config);
+    if let Some(previous) = previous {
+        let (previous_settings, previous_corrections) = info_labels(previous);
+        for (gauge, previous, current) in [
+            (&*SETTINGS_GAUGE, previous_settings, &settings),
+            (&*CORRECTION_GAUGE, previous_corrections, &corrections),
+        ] {
+            for labels in previous.iter().filter(|labels| !current.contains(labels)) {
+                let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+                let _ = gauge.remove_label_values(&labels);
+            }
+        }
+    }
+    for (gauge, current) in [
+        (&*SETTINGS_GAUGE, &settings),
+        (&*CORRECTION_GAUGE, &corrections),
+    ] {
+        for labels in current {
+            let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+            gauge.with_label_values(&labels).set(1.0);
+        }
+    }
+}
+
+/// Label values of the settings and correction info metrics of each sensor
+/// in `config`.
+fn info_labels(config: &Config) -> (Vec<Vec<String>>, Vec<Vec<String>>) {
+    let mut settings = Vec::new();
+    let mut corrections = Vec::new();
+    for spec in &config.sensors {
+        let labels = spec.labels();
+        let sensor = labels.values().map(str::to_string);
+        let spec_settings = spec.settings();
+        settings.push(
+            sensor
+                .iter()
+                .cloned()
+                .chain(
+                    [
+                        spec_settings.mode.label(),
+                        spec_settings.temperature_oversampling.label(),
+                        spec_settings.pressure_oversampling.label(),
+                        spec_settings.humidity_oversampling.label(),
+                        spec_settings.filter.label(),
+                        spec_settings.standby.label(),
+                    ]
+                    .map(str::to_string),
+                )
+                .collect(),
+        );
+
+        let correction = &spec.correction;
+        for (quantity, correction) in [
+            ("temperature", correction.temperature),
+            ("pressure", correction.pressure),
+            ("humidity", correction.humidity),
+        ] {
+            corrections.push(
+                sensor
+                    .iter()
+                    .cloned()
+                    .chain([
+                        quantity.to_string(),
+                        correction.gain.to_string(),
+                        correction.offset.to_string(),
+                    ])
+                    .collect(),
+            );
+        }
+    }
+    (settings, corrections)
+}
+
+/// Sets the gauge for `labels` to `value`, or removes it if the quantity was
+/// not measured.
+fn set_or_remove(gauge: &GaugeVec, labels: &[&str], value: f64) {
+    if value.is_nan() {
+        let _ = gauge.remove_label_values(labels);
+    } else {
+        gauge.with_label_values(labels).set(value);
+    }
+}
+
+/// Gathers the enabled metrics, named and labelled as configured.
+///
+/// Metrics of sensors which were removed from the configuration stay
+/// registered, so they are left out here.
+fn gather(active: &Active) -> Vec<MetricFamily> {
+    let config = &active.config;
+    let sensors: Vec<_> = active
+        .sensors
+        .iter()
+        .map(|latest| latest.labels().values())
+        .collect();
+
+    let mut metric_families = prometheus::gather();
+    metric_families.retain(|family| config.exporters.contains(&exporter(family.get_name())));
+
+    for family in &mut metric_families {
+        family
+            .mut_metric()
+            .retain(|metric| match sensor_labels(metric.get_label()) {
+                Some(labels) => sensors.contains(&labels),
+                None => true,
+            });
+
+        if !config.metric_prefix.is_empty() {
+            let name = format!("{}{}", config.metric_prefix, family.get_name());
+            family.set_name(name);
+        }
+        if config.labels.is_empty() {
+            continue;
+        }
+        for metric in family.mut_metric().iter_mut() {
+            for (name, value) in &config.labels {
+                // A label may only appear once, and the metric's own wins.
+                if metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.get_name() == name)
+                {
+                    continue;
+                }
+                let mut label = LabelPair::default();
+                label.set_name(name.clone());
+                label.set_value(value.clone());
+                metric.mut_label().push(label);
+            }
+            metric
+                .mut_label()
+                .sort_by(|a, b| a.get_name().cmp(b.get_name()));
+        }
+    }
+    // A family without any metrics cannot be encoded.
+    metric_families.retain(|family| !family.get_metric().is_empty());
+    metric_families
+}
+
+/// Values of the labels identifying the sensor a metric belongs to, or
+/// `None` if it does not belong to a sensor.
+fn sensor_labels(labels: &[LabelPair]) -> Option<[&str; 4]> {
+    let mut values = [""; 4];
+    for (value, name) in values.iter_mut().zip(SENSOR_LABEL_NAMES) {
+        *value = labels
+            .iter()
+            .find(|label| label.get_name() == name)?
+            .get_value();
+    }
+    Some(values)
+}
+
+/// Explains why none of the sensors has a recent reading, one line per
+/// sensor.
+fn unavailable_reason(active: &Active) -> String {
+    let reasons: Vec<String> = active
+        .sensors
+        .iter()
+        .map(|latest| {
+            let reason = match (latest.error(), latest.get()) {
+                (Some(error), _) => format!("sensor unavailable: {}", error),
+                (None, Some(sample)) => format!(
+                    "no measurement for {:.1}s, exceeding the maximum staleness of {:?}",
+                    sample.age().as_secs_f64(),
+                    active.config.max_staleness
+                ),
+                (None, None) => "no measurement taken yet".to_string(),
+            };
+            format!("{}: {}", latest.labels().name(), reason)
+        })
+        .collect();
+    reasons.join("\n")
+}
+
+fn text_response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::from(body + "\n"))
+        .unwrap()
+}
+
+impl Service<Request<Body>> for TempServer {
+    type Response = Response<Body>;
+    type Error = anyhow::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let encoding = negotiate_encoding(header(&req, ACCEPT_ENCODING));
+        let min_size = self.active.get().config.compression_min_size;
+        let response = self.route(req);
+        Box::pin(async move { Ok(compress(response.await?, encoding, min_size).await) })
+    }
+}
+
+/// Serves metrics to every connection accepted on `listener`.
+pub async fn serve(listener: TcpListener, server: TempServer) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+
+        let server = server.clone();
+        tokio::task::spawn(async {
+            if let Err(err) = Http::new().serve_connection(stream, server).await {
+                println!("Failed to serve connection: {:?}", err);
+            }
+        });
+    }
+}
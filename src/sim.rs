@@ -0,0 +1,171 @@
+use anyhow::{anyhow, bail, Context, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::path::Path;
+
+use crate::sensor::{ErrorKind, Reading, Sensor, SensorError};
+
+const TEMPERATURE_RANGE: (f64, f64) = (-40.0, 85.0);
+const PRESSURE_RANGE: (f64, f64) = (30000.0, 110000.0);
+const HUMIDITY_RANGE: (f64, f64) = (0.0, 100.0);
+
+// Largest change of each quantity between two random walk steps.
+const TEMPERATURE_STEP: f64 = 0.05;
+const PRESSURE_STEP: f64 = 5.0;
+const HUMIDITY_STEP: f64 = 0.2;
+
+/// One entry of a scripted simulation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Reading(Reading),
+    Failure(String),
+}
+
+enum Behaviour {
+    Constant(Reading),
+    RandomWalk { current: Reading, rng: Box<StdRng> },
+    Script { steps: Vec<Step>, next: usize },
+}
+
+/// A sensor which makes its readings up, for running the exporter without
+/// hardware.
+pub struct SimulatedSensor {
+    behaviour: Behaviour,
+}
+
+impl Clone for SimulatedSensor {
+    /// Copies the sensor in its current state. Random walks continue from
+    /// the same reading but drift independently of each other.
+    fn clone(&self) -> Self {
+        let behaviour = match &self.behaviour {
+            Behaviour::Constant(reading) => Behaviour::Constant(*reading),
+            Behaviour::RandomWalk { current, .. } => Behaviour::RandomWalk {
+                current: *current,
+                rng: Box::new(StdRng::from_entropy()),
+            },
+            Behaviour::Script { steps, next } => Behaviour::Script {
+                steps: steps.clone(),
+                next: *next,
+            },
+        };
+        SimulatedSensor { behaviour }
+    }
+}
+
+impl SimulatedSensor {
+    /// Always reports `reading`.
+    pub fn constant(reading: Reading) -> Self {
+        SimulatedSensor {
+            behaviour: Behaviour::Constant(reading),
+        }
+    }
+
+    /// Starts at `start` and drifts by a small random amount on every
+    /// measurement, staying within the BME280's operating range.
+    pub fn random_walk(start: Reading) -> Self {
+        SimulatedSensor {
+            behaviour: Behaviour::RandomWalk {
+                current: start,
+                rng: Box::new(StdRng::from_entropy()),
+            },
+        }
+    }
+
+    /// Replays `steps` in order, starting over once they are exhausted.
+    pub fn script(steps: Vec<Step>) -> Result<Self> {
+        if steps.is_empty() {
+            bail!("simulation script has no steps");
+        }
+        Ok(SimulatedSensor {
+            behaviour: Behaviour::Script { steps, next: 0 },
+        })
+    }
+
+    /// Reads a script from `path`.
+    ///
+    /// Each line holds either a reading as `<temperature> <pressure>
+    /// <humidity>`, or `error` followed by an optional message to simulate a
+    /// failed measurement. Blank lines and lines starting with `#` are
+    /// ignored.
+    pub fn load_script(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+        let steps = parse_script(&contents)
+            .with_context(|| format!("invalid simulation script {}", path.display()))?;
+        Self::script(steps)
+    }
+}
+
+impl Sensor for SimulatedSensor {
+    fn measure(&mut self) -> Result<Reading, SensorError> {
+        match &mut self.behaviour {
+            Behaviour::Constant(reading) => Ok(*reading),
+            Behaviour::RandomWalk { current, rng } => {
+                current.temperature = walk(
+                    rng,
+                    current.temperature,
+                    TEMPERATURE_STEP,
+                    TEMPERATURE_RANGE,
+                );
+                current.pressure = walk(rng, current.pressure, PRESSURE_STEP, PRESSURE_RANGE);
+                current.humidity = walk(rng, current.humidity, HUMIDITY_STEP, HUMIDITY_RANGE);
+                Ok(*current)
+            }
+            Behaviour::Script { steps, next } => {
+                let step = &steps[*next];
+                *next = (*next + 1) % steps.len();
+                match step {
+                    Step::Reading(reading) => Ok(*reading),
+                    Step::Failure(message) => Err(SensorError::new(
+                        ErrorKind::Other,
+                        format!("simulated failure: {}", message),
+                    )),
+                }
+            }
+        }
+    }
+}
+
+fn walk(rng: &mut StdRng, value: f64, step: f64, (min, max): (f64, f64)) -> f64 {
+    (value + rng.gen_range(-step..=step)).clamp(min, max)
+}
+
+fn parse_script(contents: &str) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let step = parse_step(line).with_context(|| format!("line {}", number + 1))?;
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+fn parse_step(line: &str) -> Result<Step> {
+    if let Some(message) = line.strip_prefix("error") {
+        return Ok(Step::Failure(message.trim().to_string()));
+    }
+
+    let values = line
+        .split_whitespace()
+        .map(|value| {
+            value
+                .parse::<f64>()
+                .map_err(|_| anyhow!("'{}' is not a number", value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    match values[..] {
+        [temperature, pressure, humidity] => Ok(Step::Reading(Reading {
+            temperature,
+            pressure,
+            humidity,
+        })),
+        _ => bail!(
+            "expected '<temperature> <pressure> <humidity>' or 'error', got '{}'",
+            line
+        ),
+    }
+}
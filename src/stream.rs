@@ -0,0 +1,120 @@
+//! Server-Sent Events of the readings, for displays which want each sample
+//! as it is taken rather than polling for it.
+//!
+//! Each event is named `reading` and carries the readings of one sensor in
+//! the same JSON as an entry of `GET /api/v1/readings`.
+
+use hyper::body::{Bytes, Sender};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Response};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::time::Instant;
+
+use crate::api::{sensor_readings, SensorReadings};
+use crate::reload::{Active, ActiveConfig};
+use crate::sampler;
+use crate::sensor::SensorLabels;
+
+pub const EVENT_STREAM_FORMAT: &str = "text/event-stream";
+
+/// How long a stream may go without sending anything. A comment is sent
+/// then, which keeps proxies from closing the connection and notices
+/// clients which have gone away.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Starts streaming events to a new client. The stream begins with the
+/// current readings of each sensor and then follows every new sample, at
+/// most one per sensor per `stream_min_interval`. It ends when the client
+/// disconnects.
+pub fn stream(active: Arc<ActiveConfig>) -> Response<Body> {
+    // Subscribe before sending the current readings, so that no sample
+    // taken in between is missed.
+    let updates = sampler::subscribe();
+    let (sender, body) = Body::channel();
+    tokio::spawn(async move {
+        // Failing to send means the client is gone, which is how every
+        // stream ends.
+        let _ = send_events(&active, updates, sender).await;
+    });
+    Response::builder()
+        .header(CONTENT_TYPE, EVENT_STREAM_FORMAT)
+        .header(CACHE_CONTROL, "no-cache")
+        .body(body)
+        .unwrap()
+}
+
+async fn send_events(
+    active: &ActiveConfig,
+    mut updates: Receiver<Arc<SensorLabels>>,
+    mut sender: Sender,
+) -> hyper::Result<()> {
+    // When the last event about each sensor was sent, by the values of its
+    // labels.
+    let mut last_sent: HashMap<[String; 4], Instant> = HashMap::new();
+
+    let current = active.get();
+    for (spec, latest) in current.config.sensors.iter().zip(&current.sensors) {
+        let readings = sensor_readings(&current, spec, latest);
+        sender.send_data(event(&readings)).await?;
+        last_sent.insert(key(latest.labels()), Instant::now());
+    }
+
+    let mut keepalive =
+        tokio::time::interval_at(Instant::now() + KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL);
+    loop {
+        tokio::select! {
+            update = updates.recv() => {
+                let labels = match update {
+                    Ok(labels) => labels,
+                    // The samples missed while the client was slow are
+                    // superseded by the next one anyway.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return Ok(()),
+                };
+                let current = active.get();
+                let key = key(&labels);
+                let min_interval = current.config.stream_min_interval;
+                if last_sent
+                    .get(&key)
+                    .is_some_and(|sent| sent.elapsed() < min_interval)
+                {
+                    continue;
+                }
+                // Sensors removed by a reload are no longer streamed.
+                let Some(readings) = find_readings(&current, &labels) else {
+                    continue;
+                };
+                sender.send_data(event(&readings)).await?;
+                last_sent.insert(key, Instant::now());
+                keepalive.reset();
+            }
+            _ = keepalive.tick() => {
+                sender.send_data(Bytes::from_static(b": keepalive\n\n")).await?;
+            }
+        }
+    }
+}
+
+fn key(labels: &SensorLabels) -> [String; 4] {
+    labels.values().map(str::to_string)
+}
+
+fn find_readings(active: &Active, labels: &SensorLabels) -> Option<SensorReadings> {
+    active
+        .config
+        .sensors
+        .iter()
+        .zip(&active.sensors)
+        .find(|(_, latest)| latest.labels() == labels)
+        .map(|(spec, latest)| sensor_readings(active, spec, latest))
+}
+
+fn event(readings: &SensorReadings) -> Bytes {
+    // Compact JSON has no line breaks, so it fits in one `data` field.
+    let data = serde_json::to_string(readings).expect("readings are always valid JSON");
+    Bytes::from(format!("event: reading\ndata: {}\n\n", data))
+}
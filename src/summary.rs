@@ -0,0 +1,154 @@
+//! Statistics of the readings over a window, so that changes between two
+//! scrapes show even when the sensor is sampled more often than it is
+//! scraped.
+
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::cli::parse_duration;
+use crate::sensor::Reading;
+
+/// The readings a [`Summary`] covers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Window {
+    /// Those since the previous scrape, by whichever client. With more than
+    /// one scraper, each only sees part of the readings.
+    #[default]
+    Scrape,
+    /// Those taken within this long before the scrape
+    Fixed(Duration),
+}
+
+impl TryFrom<String> for Window {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        if value == "scrape" {
+            Ok(Window::Scrape)
+        } else {
+            parse_duration(&value)
+                .map(Window::Fixed)
+                .map_err(|err| format!("expected `scrape` or a duration: {}", err))
+        }
+    }
+}
+
+/// Minimum, maximum and mean of the values of one quantity, which are NaN
+/// if there were none.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Statistics {
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    sum: f64,
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Statistics {
+            count: 0,
+            min: f64::NAN,
+            max: f64::NAN,
+            sum: 0.0,
+        }
+    }
+}
+
+impl Statistics {
+    /// Adds `value`, unless the quantity was not measured.
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.count += 1;
+        // `min` and `max` ignore the NaN of the first value.
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            f64::NAN
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+}
+
+/// Statistics of each of the quantities of some readings.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub temperature: Statistics,
+    pub pressure: Statistics,
+    pub humidity: Statistics,
+}
+
+impl Summary {
+    pub fn add(&mut self, reading: &Reading) {
+        self.temperature.add(reading.temperature);
+        self.pressure.add(reading.pressure);
+        self.humidity.add(reading.humidity);
+    }
+}
+
+/// Keeps what is needed to summarise the readings of one sensor over a
+/// [`Window`].
+#[derive(Debug)]
+pub struct Recorder {
+    window: Window,
+    /// Readings since the previous scrape, when the window ends with it
+    since_scrape: Summary,
+    /// Readings within the window, when it is fixed
+    recent: VecDeque<(Instant, Reading)>,
+}
+
+impl Recorder {
+    pub fn new(window: Window) -> Self {
+        Recorder {
+            window,
+            since_scrape: Summary::default(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Records `reading`, taken at `taken`. Readings must be pushed in
+    /// order.
+    pub fn push(&mut self, taken: Instant, reading: Reading) {
+        match self.window {
+            Window::Scrape => self.since_scrape.add(&reading),
+            Window::Fixed(window) => {
+                self.recent.push_back((taken, reading));
+                self.forget(taken, window);
+            }
+        }
+    }
+
+    /// The summary of the readings in the window for a scrape at `now`.
+    /// When the window ends with each scrape, this starts the next one.
+    pub fn summarize(&mut self, now: Instant) -> Summary {
+        match self.window {
+            Window::Scrape => std::mem::take(&mut self.since_scrape),
+            Window::Fixed(window) => {
+                self.forget(now, window);
+                let mut summary = Summary::default();
+                for (_, reading) in &self.recent {
+                    summary.add(reading);
+                }
+                summary
+            }
+        }
+    }
+
+    /// Forgets readings taken more than `window` before `now`.
+    fn forget(&mut self, now: Instant, window: Duration) {
+        while let Some(&(taken, _)) = self.recent.front() {
+            if now.saturating_duration_since(taken) <= window {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! How the pressure changed over the last few hours, the most useful sign of
+//! coming weather.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// Period the WMO pressure tendency is reported over.
+const TENDENCY_PERIOD: Duration = Duration::from_secs(3 * 3600);
+
+/// Interval between the samples kept in the history, which bounds its size
+/// however often the sensor is sampled.
+const RESOLUTION: Duration = Duration::from_secs(60);
+
+/// Changes smaller than this, in Pascals, count as steady. Synoptic reports
+/// give the tendency in tenths of a hectopascal.
+const STEADY: f64 = 10.0;
+
+/// How the pressure changed up to the time of a sample. Each part is `None`
+/// until there is enough history.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tendency {
+    /// Change over the last hour in Pascals
+    pub change_1h: Option<f64>,
+    /// Change over the last three hours in Pascals
+    pub change_3h: Option<f64>,
+    /// Characteristic of the change over the last three hours, by WMO code
+    /// table 0200
+    pub code: Option<u8>,
+}
+
+/// Pressure samples of the last three hours, about one a minute.
+#[derive(Debug, Default)]
+pub struct PressureHistory {
+    samples: VecDeque<(Instant, f64)>,
+    latest: Option<(Instant, f64)>,
+}
+
+impl PressureHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `pressure` in Pascals measured at `taken`, forgetting samples
+    /// no longer needed. Samples must be pushed in order.
+    pub fn push(&mut self, taken: Instant, pressure: f64) {
+        if pressure.is_nan() {
+            return;
+        }
+        self.latest = Some((taken, pressure));
+        if let Some(&(previous, _)) = self.samples.back() {
+            if taken.duration_since(previous) < RESOLUTION {
+                return;
+            }
+        }
+        while let Some(&(oldest, _)) = self.samples.front() {
+            if taken.duration_since(oldest) <= TENDENCY_PERIOD + tolerance(TENDENCY_PERIOD) {
+                break;
+            }
+            self.samples.pop_front();
+        }
+        self.samples.push_back((taken, pressure));
+    }
+
+    /// The tendency up to the latest sample.
+    pub fn tendency(&self) -> Tendency {
+        let Some((now, pressure)) = self.latest else {
+            return Tendency::default();
+        };
+        let change = |period| self.at(now, period).map(|before| pressure - before);
+
+        let code = match (
+            self.at(now, TENDENCY_PERIOD),
+            self.at(now, TENDENCY_PERIOD / 2),
+        ) {
+            (Some(start), Some(middle)) => Some(code(middle - start, pressure - middle)),
+            _ => None,
+        };
+        Tendency {
+            change_1h: change(HOUR),
+            change_3h: change(TENDENCY_PERIOD),
+            code,
+        }
+    }
+
+    /// The pressure `period` before `now`, if there is a sample close enough
+    /// to that time.
+    fn at(&self, now: Instant, period: Duration) -> Option<f64> {
+        let target = now.checked_sub(period)?;
+        self.samples
+            .iter()
+            .map(|&(taken, pressure)| (distance(taken, target), pressure))
+            .filter(|&(distance, _)| distance <= tolerance(period))
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(_, pressure)| pressure)
+    }
+}
+
+/// How far a sample may be from the time it stands in for, which covers
+/// sampling intervals and short gaps.
+fn tolerance(period: Duration) -> Duration {
+    period / 20
+}
+
+fn distance(a: Instant, b: Instant) -> Duration {
+    a.max(b).duration_since(a.min(b))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Trend {
+    Rising,
+    Steady,
+    Falling,
+}
+
+fn trend(change: f64) -> Trend {
+    if change >= STEADY {
+        Trend::Rising
+    } else if change <= -STEADY {
+        Trend::Falling
+    } else {
+        Trend::Steady
+    }
+}
+
+/// WMO code table 0200 for a pressure which changed by `first` in the first
+/// half of the period and `second` in the second half.
+fn code(first: f64, second: f64) -> u8 {
+    use Trend::*;
+
+    match (trend(first + second), trend(first), trend(second)) {
+        // The same as at the start of the period
+        (Steady, Rising, Falling) => 0,
+        (Steady, Falling, Rising) => 5,
+        (Steady, _, _) => 4,
+        // Higher
+        (Rising, Rising, Falling) => 0,
+        (Rising, Rising, Steady) => 1,
+        (Rising, Rising, Rising) if second < first / 2.0 => 1,
+        (Rising, Rising, Rising) if second > first * 2.0 => 3,
+        (Rising, Steady | Falling, Rising) => 3,
+        (Rising, _, _) => 2,
+        // Lower
+        (Falling, Falling, Rising) => 5,
+        (Falling, Falling, Steady) => 6,
+        (Falling, Falling, Falling) if second > first / 2.0 => 6,
+        (Falling, Falling, Falling) if second < first * 2.0 => 8,
+        (Falling, Steady | Rising, Falling) => 8,
+        (Falling, _, _) => 7,
+    }
+}
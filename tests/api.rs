@@ -0,0 +1,114 @@
+mod common;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::StatusCode;
+use serde_json::Value;
+
+use common::{
+    chip, get_with_headers, near, post, sample, scrape_until, serve, start_sensors, test_config,
+};
+use prometheus_bme280_exporter::cli::FailureResponse;
+use prometheus_bme280_exporter::config::{Config, Exporter};
+use prometheus_bme280_exporter::sensor::Reading;
+
+#[tokio::test]
+async fn serves_readings_as_json() {
+    let reading = Reading {
+        temperature: 23.25,
+        pressure: 98765.0,
+        humidity: 61.5,
+    };
+    let indoor = chip(reading);
+    let outdoor = chip(reading);
+    outdoor.set_connected(false);
+    let addr = start_sensors(
+        vec![("indoor", indoor), ("outdoor", outdoor)],
+        FailureResponse::Unavailable,
+    )
+    .await;
+    let (_, metrics) = scrape_until(addr, |status, body| {
+        status == StatusCode::OK
+            && body.contains(
+                r#"bme280_up{address="0x76",bus="emulated",location="",sensor="outdoor"} 0"#,
+            )
+    })
+    .await;
+
+    let response = get_with_headers(addr, "/api/v1/readings", &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    let json: Value = serde_json::from_slice(response.body()).unwrap();
+
+    let indoor = &json["sensors"][0];
+    assert_eq!(indoor["name"], "indoor");
+    assert_eq!(indoor["bus"], "emulated");
+    assert_eq!(indoor["address"], "0x76");
+    assert_eq!(indoor["up"], true);
+    assert_eq!(indoor["error"], Value::Null);
+    assert!(indoor["timestamp"].as_f64().unwrap() > 0.0);
+    assert!(indoor["age_seconds"].as_f64().unwrap() < 1.0);
+    // The same values as the metrics, which come from the same sample.
+    for (group, key, metric) in [
+        (
+            "readings",
+            "temperature_celsius",
+            "meter_temperature_celsius",
+        ),
+        ("readings", "pressure_pascals", "meter_pressure_pascals"),
+        ("readings", "humidity_percent", "meter_humidity_percent"),
+        ("derived", "dew_point_celsius", "meter_dew_point_celsius"),
+    ] {
+        let value = indoor[group][key].as_f64().unwrap();
+        let metric = sample(&metrics, &format!(r#"{}{{sensor="indoor"}}"#, metric));
+        assert!(
+            near(metric, value, 0.05),
+            "{}: {} != {:?}",
+            key,
+            value,
+            metric
+        );
+    }
+    // Only quantities which are exported are included.
+    assert_eq!(indoor["raw"], serde_json::json!({}));
+    assert_eq!(indoor["derived"]["heat_index_celsius"], Value::Null);
+
+    let outdoor = &json["sensors"][1];
+    assert_eq!(outdoor["name"], "outdoor");
+    assert_eq!(outdoor["up"], false);
+    assert!(outdoor["error"].as_str().is_some(), "{}", outdoor);
+    assert_eq!(outdoor["timestamp"], Value::Null);
+    assert_eq!(outdoor["readings"], serde_json::json!({}));
+
+    let (status, _) = post(addr, "/api/v1/readings").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn includes_raw_readings_when_exported() {
+    let chip = chip(Reading {
+        temperature: 18.0,
+        pressure: 99000.0,
+        humidity: 55.0,
+    });
+    let config = Config {
+        exporters: vec![Exporter::Readings, Exporter::Raw],
+        ..test_config()
+    };
+    let addr = serve(vec![("raw", chip)], config).await;
+    scrape_until(addr, |status, _| status == StatusCode::OK).await;
+
+    let response = get_with_headers(addr, "/api/v1/readings", &[]).await;
+    let json: Value = serde_json::from_slice(response.body()).unwrap();
+    let raw = &json["sensors"][0]["raw"];
+    assert!(
+        near(raw["temperature_celsius"].as_f64(), 18.0, 0.05),
+        "{}",
+        raw
+    );
+    assert!(
+        near(raw["pressure_pascals"].as_f64(), 99000.0, 2.0),
+        "{}",
+        raw
+    );
+    assert!(near(raw["humidity_percent"].as_f64(), 55.0, 0.1), "{}", raw);
+}
@@ -0,0 +1,110 @@
+use clap::Parser;
+use std::time::Duration;
+
+use prometheus_bme280_exporter::cli::{parse_duration, parse_sensor, Args, SensorAddress};
+use prometheus_bme280_exporter::config::Config;
+
+#[test]
+fn parses_sensors() {
+    let spec = parse_sensor("name=indoor").unwrap();
+    assert_eq!(spec.name, "indoor");
+    assert_eq!(spec.device, "/dev/i2c-1");
+    assert_eq!(spec.address, SensorAddress::Primary);
+    assert_eq!(spec.location, "");
+
+    let spec =
+        parse_sensor("location=balcony,address=secondary,device=/dev/i2c-2,name=outdoor").unwrap();
+    assert_eq!(spec.name, "outdoor");
+    assert_eq!(spec.device, "/dev/i2c-2");
+    assert_eq!(spec.address, SensorAddress::Secondary);
+    assert_eq!(spec.location, "balcony");
+}
+
+#[test]
+fn rejects_invalid_sensors() {
+    for (value, message) in [
+        ("", "expected 'key=value', got ''"),
+        ("indoor", "expected 'key=value', got 'indoor'"),
+        ("name=indoor,", "expected 'key=value', got ''"),
+        (
+            "name=indoor,,device=/dev/i2c-1",
+            "expected 'key=value', got ''",
+        ),
+        ("name=", "sensor name is empty"),
+        ("device=/dev/i2c-1", "sensor has no name"),
+        ("name=indoor,bus=1", "unknown sensor setting 'bus'"),
+        (
+            "name=indoor,name=outdoor",
+            "sensor setting 'name' is given twice",
+        ),
+        (
+            "name=indoor,address=primary,address=secondary",
+            "sensor setting 'address' is given twice",
+        ),
+        (
+            "name=indoor,address=0x78",
+            "unknown address '0x78', expected 'primary' or 'secondary'",
+        ),
+        (
+            "name=indoor,address=tertiary",
+            "unknown address 'tertiary', expected 'primary' or 'secondary'",
+        ),
+        (
+            "name=indoor,address=",
+            "unknown address '', expected 'primary' or 'secondary'",
+        ),
+    ] {
+        assert_eq!(parse_sensor(value).unwrap_err(), message, "{}", value);
+    }
+}
+
+#[test]
+fn rejects_duplicate_sensor_names() {
+    let args = Args::try_parse_from([
+        "prometheus-bme280-exporter",
+        "--sensor",
+        "name=indoor",
+        "--sensor",
+        "name=indoor,address=secondary",
+    ])
+    .unwrap();
+    let message = format!("{:#}", Config::from_args(&args).unwrap_err());
+    assert_eq!(
+        message,
+        "sensor[1].name: 'indoor' is already the name of another sensor"
+    );
+}
+
+#[test]
+fn parses_durations() {
+    assert_eq!(parse_duration("5"), Ok(Duration::from_secs(5)));
+    assert_eq!(parse_duration("2.5s"), Ok(Duration::from_millis(2500)));
+    assert_eq!(parse_duration("0.5ms"), Ok(Duration::from_micros(500)));
+    assert_eq!(parse_duration("1m"), Ok(Duration::from_secs(60)));
+    assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+}
+
+#[test]
+fn rejects_invalid_durations() {
+    for (value, message) in [
+        ("soon", "'soon' is not a duration"),
+        ("", "'' is not a duration"),
+        ("5d", "unknown unit 'd' in '5d'"),
+        ("0", "'0' is not a positive duration"),
+        ("0ms", "'0ms' is not a positive duration"),
+        // Shorter than a nanosecond
+        ("0.0000000001", "'0.0000000001' is not a positive duration"),
+        // Longer than a `Duration` can be
+        (
+            "99999999999999999999999",
+            "'99999999999999999999999' is out of range",
+        ),
+        ("99999999999999999h", "'99999999999999999h' is out of range"),
+        // Not a number as far as durations go
+        ("-1", "'-1' is not a duration"),
+        ("NaN", "'NaN' is not a duration"),
+        ("inf", "'inf' is not a duration"),
+    ] {
+        assert_eq!(parse_duration(value), Err(message.to_string()), "{}", value);
+    }
+}
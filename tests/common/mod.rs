@@ -0,0 +1,210 @@
+// Each test binary uses a different subset of these helpers.
+#![allow(dead_code)]
+
+use hyper::{Body, Client, Method, Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+use prometheus_bme280_exporter::cli::{FailureResponse, SensorAddress};
+use prometheus_bme280_exporter::config::{Config, SensorSpec};
+use prometheus_bme280_exporter::emulator::EmulatedBme280;
+use prometheus_bme280_exporter::recovery::{RecoveringSensor, RecoveryPolicy};
+use prometheus_bme280_exporter::reload::ActiveConfig;
+use prometheus_bme280_exporter::sampler;
+use prometheus_bme280_exporter::sensor::{Bme280Sensor, Reading};
+use prometheus_bme280_exporter::server::{self, TempServer};
+
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+pub const MAX_STALENESS: Duration = Duration::from_millis(300);
+
+/// An emulated chip at the primary address reporting `reading`.
+pub fn chip(reading: Reading) -> EmulatedBme280 {
+    EmulatedBme280::new(SensorAddress::Primary.value(), reading)
+}
+
+/// Starts the exporter on an ephemeral port, reading from `chip`.
+pub async fn start(chip: EmulatedBme280, on_failure: FailureResponse) -> SocketAddr {
+    start_sensors(vec![("bme280", chip)], on_failure).await
+}
+
+/// Starts the exporter on an ephemeral port, reading from each of `chips`
+/// at the primary address under the given name.
+pub async fn start_sensors(
+    chips: Vec<(&str, EmulatedBme280)>,
+    on_failure: FailureResponse,
+) -> SocketAddr {
+    serve(
+        chips,
+        Config {
+            on_failure,
+            ..test_config()
+        },
+    )
+    .await
+}
+
+/// Settings suitable for tests, where everything happens quickly. There are
+/// no sensors but those [`serve`] adds for its chips.
+pub fn test_config() -> Config {
+    Config {
+        sensors: Vec::new(),
+        sample_interval: SAMPLE_INTERVAL,
+        max_staleness: MAX_STALENESS,
+        recovery: RecoveryPolicy {
+            failures: 1,
+            initial_backoff: SAMPLE_INTERVAL,
+            max_backoff: SAMPLE_INTERVAL,
+        },
+        ..Config::default()
+    }
+}
+
+/// Starts the exporter on an ephemeral port with `config`, reading from each
+/// of `chips` under the given name. A chip is read as the sensor of the same
+/// name in `config`, if there is one, and otherwise as a sensor with default
+/// settings on a bus called `emulated`.
+pub async fn serve(chips: Vec<(&str, EmulatedBme280)>, config: Config) -> SocketAddr {
+    let sensors = chips
+        .iter()
+        .map(|(name, _)| {
+            config
+                .sensors
+                .iter()
+                .find(|spec| spec.name == *name)
+                .cloned()
+                .unwrap_or_else(|| SensorSpec {
+                    device: "emulated".to_string(),
+                    ..SensorSpec::new(name)
+                })
+        })
+        .collect();
+    start_active(Config { sensors, ..config }, None, chips).await
+}
+
+/// Starts the exporter on an ephemeral port as configured in the file at
+/// `path`, reading each configured sensor from the chip of the same name in
+/// `chips`.
+pub async fn start_from_file(path: &Path, chips: Vec<(&str, EmulatedBme280)>) -> SocketAddr {
+    let config = Config::load(path).unwrap();
+    start_active(config, Some(path.to_path_buf()), chips).await
+}
+
+async fn start_active(
+    config: Config,
+    path: Option<PathBuf>,
+    chips: Vec<(&str, EmulatedBme280)>,
+) -> SocketAddr {
+    let chips: HashMap<String, EmulatedBme280> = chips
+        .into_iter()
+        .map(|(name, chip)| (name.to_string(), chip))
+        .collect();
+    let spawn = move |spec: &SensorSpec, config: &Config| {
+        let chip = chips[&spec.name].clone();
+        let address = spec.address;
+        let settings = spec.settings();
+        let sensor = RecoveringSensor::new(
+            move || Bme280Sensor::with_settings(chip.clone(), address, settings),
+            config.recovery,
+            &spec.labels(),
+        );
+        sampler::spawn(sensor, config, spec)
+    };
+    let active = ActiveConfig::start(config, path, Box::new(spawn));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::serve(listener, TempServer::new(active)));
+    addr
+}
+
+pub async fn get(addr: SocketAddr, path: &str) -> (StatusCode, String) {
+    request(addr, Method::GET, path).await
+}
+
+pub async fn post(addr: SocketAddr, path: &str) -> (StatusCode, String) {
+    request(addr, Method::POST, path).await
+}
+
+/// Gets `path` with the request `headers`, returning the response with its
+/// body.
+pub async fn get_with_headers(
+    addr: SocketAddr,
+    path: &str,
+    headers: &[(&str, &str)],
+) -> Response<Vec<u8>> {
+    let mut request = Request::builder().uri(format!("http://{}{}", addr, path));
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = Client::new()
+        .request(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let (parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap();
+    Response::from_parts(parts, body.to_vec())
+}
+
+async fn request(addr: SocketAddr, method: Method, path: &str) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method(method)
+        .uri(format!("http://{}{}", addr, path))
+        .body(Body::empty())
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// Value of the first sample in a text exposition matching `selector`, a
+/// metric name optionally followed by some of its labels, e.g.
+/// `bme280_up{sensor="indoor"}`.
+pub fn sample(body: &str, selector: &str) -> Option<f64> {
+    let (name, labels) = split_labels(selector);
+    body.lines()
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| {
+            let (series, value) = line.rsplit_once(' ')?;
+            let (series_name, series_labels) = split_labels(series);
+            (series_name == name && labels.iter().all(|label| series_labels.contains(label)))
+                .then(|| value.parse().unwrap())
+        })
+}
+
+fn split_labels(series: &str) -> (&str, Vec<&str>) {
+    match series.split_once('{') {
+        Some((name, labels)) => (
+            name,
+            labels
+                .trim_end_matches('}')
+                .split(',')
+                .filter(|label| !label.is_empty())
+                .collect(),
+        ),
+        None => (series, Vec::new()),
+    }
+}
+
+/// Scrapes until the response satisfies `condition`, giving up after a
+/// second.
+pub async fn scrape_until(
+    addr: SocketAddr,
+    condition: impl Fn(StatusCode, &str) -> bool,
+) -> (StatusCode, String) {
+    for _ in 0..20 {
+        let (status, body) = get(addr, "/metrics").await;
+        if condition(status, &body) {
+            return (status, body);
+        }
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+    }
+    panic!("scrape never returned the expected response");
+}
+
+pub fn near(value: Option<f64>, expected: f64, tolerance: f64) -> bool {
+    value.is_some_and(|value| (value - expected).abs() < tolerance)
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use hyper::header::{HeaderName, CONTENT_ENCODING, VARY};
+use hyper::{Response, StatusCode};
+use std::io::Read;
+
+use common::{chip, get, get_with_headers, scrape_until, serve, test_config};
+use prometheus_bme280_exporter::config::Config;
+use prometheus_bme280_exporter::sensor::Reading;
+
+fn header(response: &Response<Vec<u8>>, name: HeaderName) -> Option<&str> {
+    response
+        .headers()
+        .get(name)
+        .map(|value| value.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn compresses_responses() {
+    let chip = chip(Reading {
+        temperature: 23.25,
+        pressure: 98765.0,
+        humidity: 61.5,
+    });
+    let config = Config {
+        compression_min_size: 200,
+        ..test_config()
+    };
+    let addr = serve(vec![("bme280", chip)], config).await;
+    scrape_until(addr, |status, _| status == StatusCode::OK).await;
+    let (_, text) = get(addr, "/metrics").await;
+    assert!(text.len() > 200);
+
+    let response = get_with_headers(addr, "/metrics", &[]).await;
+    assert_eq!(header(&response, CONTENT_ENCODING), None);
+    assert_eq!(header(&response, VARY), Some("Accept, Accept-Encoding"));
+    assert!(String::from_utf8_lossy(response.body()).contains("meter_temperature_celsius"));
+
+    let response =
+        get_with_headers(addr, "/metrics", &[("Accept-Encoding", "gzip, deflate")]).await;
+    assert_eq!(header(&response, CONTENT_ENCODING), Some("gzip"));
+    let mut body = String::new();
+    GzDecoder::new(response.body().as_slice())
+        .read_to_string(&mut body)
+        .unwrap();
+    assert!(body.contains("meter_temperature_celsius"), "{}", body);
+    assert!(response.body().len() < body.len());
+
+    let response = get_with_headers(
+        addr,
+        "/metrics",
+        &[("Accept-Encoding", "gzip;q=0, deflate;q=0.5, br")],
+    )
+    .await;
+    assert_eq!(header(&response, CONTENT_ENCODING), Some("deflate"));
+    let mut body = String::new();
+    ZlibDecoder::new(response.body().as_slice())
+        .read_to_string(&mut body)
+        .unwrap();
+    assert!(body.contains("meter_temperature_celsius"), "{}", body);
+
+    let response = get_with_headers(addr, "/metrics", &[("Accept-Encoding", "br")]).await;
+    assert_eq!(header(&response, CONTENT_ENCODING), None);
+
+    // Any encoding is one which is not refused.
+    for (accept, encoding) in [
+        ("*", Some("gzip")),
+        ("*;q=1, gzip;q=0", Some("deflate")),
+        ("gzip;q=0, *", Some("deflate")),
+        ("*, gzip;q=0, deflate;q=0", None),
+        ("*;q=0", None),
+    ] {
+        let response = get_with_headers(addr, "/metrics", &[("Accept-Encoding", accept)]).await;
+        assert_eq!(header(&response, CONTENT_ENCODING), encoding, "{}", accept);
+    }
+
+    // Short responses are not worth compressing.
+    let response = get_with_headers(addr, "/nothing", &[("Accept-Encoding", "gzip")]).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(header(&response, CONTENT_ENCODING), None);
+    assert_eq!(header(&response, VARY), Some("Accept-Encoding"));
+}
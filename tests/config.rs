@@ -0,0 +1,254 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{chip, sample, scrape_until, serve, test_config};
+use prometheus_bme280_exporter::cli::{FailureResponse, SensorAddress};
+use prometheus_bme280_exporter::config::{Config, Exporter};
+use prometheus_bme280_exporter::driver::{Filter, Mode, Oversampling, Standby};
+use prometheus_bme280_exporter::psychrometrics::Quantity;
+use prometheus_bme280_exporter::sensor::Reading;
+use prometheus_bme280_exporter::summary::Window;
+
+fn error(contents: &str) -> String {
+    format!("{:#}", Config::parse(contents).unwrap_err())
+}
+
+#[test]
+fn empty_file_uses_defaults() {
+    let config = Config::parse("").unwrap();
+
+    assert_eq!(config.listen_address, "0.0.0.0:3002".parse().unwrap());
+    assert_eq!(config.sample_interval, Duration::from_secs(5));
+    assert_eq!(config.on_failure, FailureResponse::Unavailable);
+    assert_eq!(config.exporters, [Exporter::Readings, Exporter::Health]);
+    assert_eq!(config.summary_window, Window::Scrape);
+    assert_eq!(config.stream_min_interval, Duration::from_secs(1));
+    assert_eq!(config.sensors.len(), 1);
+    assert_eq!(config.sensors[0].device, "/dev/i2c-1");
+    assert_eq!(config.sensors[0].address, SensorAddress::Primary);
+}
+
+#[test]
+fn parses_full_file() {
+    let config = Config::parse(
+        r#"
+        listen_address = "127.0.0.1:9100"
+        metric_prefix = "home_"
+        sample_interval = "500ms"
+        max_staleness = "1m"
+        on_failure = "down"
+        exporters = ["readings"]
+        summary_window = "5m"
+        compression_min_size = 0
+        stream_min_interval = "250ms"
+        psychrometrics = ["vapor-pressure-deficit", "specific-humidity"]
+
+        [labels]
+        site = "cabin"
+
+        [recovery]
+        reinit_after = 5
+        backoff = "2s"
+        max_backoff = "1h"
+
+        [[sensor]]
+        name = "indoor"
+        location = "kitchen"
+
+        [[sensor]]
+        name = "outdoor"
+        device = "/dev/i2c-0"
+        address = "secondary"
+        mode = "normal"
+        filter = "off"
+        standby = "125ms"
+
+        [sensor.oversampling]
+        humidity = "16x"
+        pressure = "skip"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.listen_address, "127.0.0.1:9100".parse().unwrap());
+    assert_eq!(config.metric_prefix, "home_");
+    assert_eq!(config.sample_interval, Duration::from_millis(500));
+    assert_eq!(config.max_staleness, Duration::from_secs(60));
+    assert_eq!(config.on_failure, FailureResponse::Down);
+    assert_eq!(config.exporters, [Exporter::Readings]);
+    assert_eq!(
+        config.summary_window,
+        Window::Fixed(Duration::from_secs(300))
+    );
+    assert_eq!(config.compression_min_size, 0);
+    assert_eq!(config.stream_min_interval, Duration::from_millis(250));
+    assert_eq!(
+        config.psychrometrics,
+        [Quantity::VaporPressureDeficit, Quantity::SpecificHumidity]
+    );
+    assert_eq!(config.labels["site"], "cabin");
+    assert_eq!(config.recovery.failures, 5);
+    assert_eq!(config.recovery.initial_backoff, Duration::from_secs(2));
+    assert_eq!(config.recovery.max_backoff, Duration::from_secs(3600));
+    assert_eq!(config.sensors[0].name, "indoor");
+    assert_eq!(config.sensors[0].location, "kitchen");
+    assert_eq!(config.sensors[1].device, "/dev/i2c-0");
+    assert_eq!(config.sensors[1].address, SensorAddress::Secondary);
+    assert_eq!(config.sensors[0].settings(), Default::default());
+    let settings = config.sensors[1].settings();
+    assert_eq!(settings.mode, Mode::Normal);
+    assert_eq!(settings.temperature_oversampling, Oversampling::X2);
+    assert_eq!(settings.pressure_oversampling, Oversampling::Skip);
+    assert_eq!(settings.humidity_oversampling, Oversampling::X16);
+    assert_eq!(settings.filter, Filter::Off);
+    assert_eq!(settings.standby, Standby::Ms125);
+}
+
+#[test]
+fn parses_documented_example() {
+    let source = include_str!("../src/config.rs");
+    let example: String = source
+        .lines()
+        .skip_while(|line| *line != "//! ```toml")
+        .skip(1)
+        .take_while(|line| *line != "//! ```")
+        .map(|line| format!("{}\n", line.trim_start_matches("//!").trim_start()))
+        .collect();
+    assert!(!example.is_empty());
+
+    let config = Config::parse(&example).unwrap();
+    assert_eq!(
+        config.psychrometrics,
+        [Quantity::VaporPressureDeficit, Quantity::AbsoluteHumidity]
+    );
+    assert_eq!(config.labels.len(), 1);
+}
+
+#[test]
+fn rejects_invalid_values() {
+    let message = error("[[sensor]]\nname = \"a\"\naddress = \"tertiary\"\n");
+    assert!(message.contains("line 3"), "{}", message);
+    assert!(message.contains("tertiary"), "{}", message);
+
+    let message = error("sample_interval = \"soon\"\n");
+    assert!(message.contains("line 1"), "{}", message);
+    assert!(message.contains("'soon' is not a duration"), "{}", message);
+
+    let message = error("summary_window = \"hourly\"\n");
+    assert!(
+        message.contains("expected `scrape` or a duration"),
+        "{}",
+        message
+    );
+
+    let message = error("[[sensor]]\nname = \"a\"\nbus = \"/dev/i2c-0\"\n");
+    assert!(message.contains("unknown field `bus`"), "{}", message);
+}
+
+#[test]
+fn rejects_inconsistent_settings() {
+    assert!(
+        error("[[sensor]]\nname = \"a\"\n[[sensor]]\nname = \"a\"\naddress = \"secondary\"\n")
+            .contains("sensor[1].name")
+    );
+    assert!(
+        error("[[sensor]]\nname = \"a\"\n[[sensor]]\nname = \"b\"\n").contains("sensor[1].address")
+    );
+    assert!(error("[labels]\nsensor = \"x\"\n").contains("labels.sensor"));
+    assert!(error("[labels]\n\"1st\" = \"x\"\n").contains("labels.1st"));
+    assert!(error("[labels]\n__name__ = \"x\"\n").contains("labels.__name__"));
+    for name in [
+        "kind",
+        "quantity",
+        "reason",
+        "mode",
+        "temperature_oversampling",
+        "pressure_oversampling",
+        "humidity_oversampling",
+        "filter",
+        "standby",
+        "gain",
+        "offset",
+        "le",
+    ] {
+        let message = error(&format!("[labels]\n{} = \"x\"\n", name));
+        assert!(
+            message.contains(&format!("labels.{}: '{}' is already used", name, name)),
+            "{}",
+            message
+        );
+    }
+    assert!(error("metric_prefix = \"my-\"\n").contains("metric_prefix"));
+    assert!(error("[recovery]\nreinit_after = 0\n").contains("recovery.reinit_after"));
+    assert!(error("[magnus]\nc = -243.12\n").contains("magnus.c"));
+    assert!(
+        error("[[sensor]]\nname = \"a\"\nsea_level_pressure = 0.0\n")
+            .contains("sensor[0].sea_level_pressure")
+    );
+    assert!(error(
+        "[[sensor]]\nname = \"a\"\n[sensor.limits]\nhumidity = { min = 50.0, max = 10.0 }\n"
+    )
+    .contains("sensor[0].limits.humidity"));
+    assert!(
+        error("[[sensor]]\nname = \"a\"\n[sensor.smoothing]\nmedian = 0\n")
+            .contains("sensor[0].smoothing.median")
+    );
+    assert!(
+        error("[[sensor]]\nname = \"a\"\n[sensor.smoothing]\nema = 1.5\n")
+            .contains("sensor[0].smoothing.ema")
+    );
+    assert!(error("sensor = []\n").contains("at least one sensor"));
+    assert!(
+        error("[[sensor]]\nname = \"a\"\n[sensor.oversampling]\ntemperature = \"skip\"\n")
+            .contains("sensor[0].oversampling.temperature")
+    );
+}
+
+#[tokio::test]
+async fn names_and_labels_metrics_as_configured() {
+    let chip = chip(Reading {
+        temperature: 18.0,
+        pressure: 99000.0,
+        humidity: 55.0,
+    });
+    let mut config = Config {
+        metric_prefix: "home_".to_string(),
+        exporters: vec![Exporter::Readings, Exporter::Summary],
+        ..test_config()
+    };
+    config
+        .labels
+        .insert("site".to_string(), "cabin".to_string());
+    // Validation would refuse this, but should it get through, the label of
+    // the metric itself must not be repeated.
+    config
+        .labels
+        .insert("quantity".to_string(), "all".to_string());
+    let addr = serve(vec![("bme280", chip)], config).await;
+
+    let (_, body) = scrape_until(addr, |_, body| {
+        sample(body, "home_meter_temperature_celsius").is_some()
+    })
+    .await;
+    assert!(sample(
+        &body,
+        r#"home_meter_pressure_pascals{site="cabin",sensor="bme280"}"#
+    )
+    .is_some());
+    assert_eq!(sample(&body, "meter_temperature_celsius"), None);
+    assert!(!body.contains("bme280_up"), "{}", body);
+    assert!(sample(
+        &body,
+        r#"home_meter_pressure_pascals{quantity="all",site="cabin"}"#
+    )
+    .is_some());
+    for line in body
+        .lines()
+        .filter(|line| line.starts_with("home_meter_summary_samples"))
+    {
+        assert_eq!(line.matches("quantity=").count(), 1, "{}", line);
+        assert!(!line.contains(r#"quantity="all""#), "{}", line);
+    }
+    assert!(body.contains("home_meter_summary_samples{"), "{}", body);
+}
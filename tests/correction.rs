@@ -0,0 +1,112 @@
+mod common;
+
+use common::{chip, near, sample, scrape_until, serve, test_config};
+use prometheus_bme280_exporter::config::{Config, Exporter};
+use prometheus_bme280_exporter::correction::Correction;
+use prometheus_bme280_exporter::sensor::Reading;
+
+fn correction(contents: &str) -> Result<Correction, String> {
+    let config = format!(
+        "[[sensor]]\nname = \"a\"\n[sensor.correction]\ntemperature = {}\n",
+        contents
+    );
+    Config::parse(&config)
+        .map(|config| config.sensors[0].correction.temperature)
+        .map_err(|err| format!("{:#}", err))
+}
+
+#[test]
+fn parses_corrections() {
+    assert_eq!(
+        correction("{ offset = -1.5 }").unwrap(),
+        Correction {
+            gain: 1.0,
+            offset: -1.5
+        }
+    );
+    assert_eq!(
+        correction("{ offset = 2.0, gain = 0.5 }").unwrap(),
+        Correction {
+            gain: 0.5,
+            offset: 2.0
+        }
+    );
+
+    let two_point = correction("{ raw = [10.0, 30.0], reference = [9.0, 31.0] }").unwrap();
+    assert!((two_point.apply(10.0) - 9.0).abs() < 1e-9);
+    assert!((two_point.apply(30.0) - 31.0).abs() < 1e-9);
+    assert!((two_point.gain - 1.1).abs() < 1e-9);
+
+    let message =
+        correction("{ offset = 1.0, raw = [1.0, 2.0], reference = [1.0, 2.0] }").unwrap_err();
+    assert!(
+        message.contains("either `offset` and `gain`"),
+        "{}",
+        message
+    );
+    let message = correction("{ raw = [1.0, 1.0], reference = [1.0, 2.0] }").unwrap_err();
+    assert!(message.contains("must differ"), "{}", message);
+    let message = correction("{ gain = -1.0 }").unwrap_err();
+    assert!(message.contains("gain must be positive"), "{}", message);
+}
+
+#[tokio::test]
+async fn exports_corrected_and_raw_readings() {
+    let chip = chip(Reading {
+        temperature: 24.0,
+        pressure: 100000.0,
+        humidity: 98.0,
+    });
+    let parsed = Config::parse(
+        r#"
+        [[sensor]]
+        name = "hvac"
+        device = "emulated"
+
+        [sensor.correction]
+        temperature = { offset = -1.5 }
+        humidity = { offset = 5.0 }
+        "#,
+    )
+    .unwrap();
+    let config = Config {
+        exporters: vec![Exporter::Readings, Exporter::Raw, Exporter::Health],
+        sensors: parsed.sensors,
+        ..test_config()
+    };
+    let addr = serve(vec![("hvac", chip)], config).await;
+
+    let (_, body) = scrape_until(addr, |_, body| {
+        sample(body, "meter_temperature_celsius").is_some()
+    })
+    .await;
+    assert!(near(sample(&body, "meter_temperature_celsius"), 22.5, 0.05));
+    assert!(near(
+        sample(&body, "meter_raw_temperature_celsius"),
+        24.0,
+        0.05
+    ));
+    assert!(near(sample(&body, "meter_pressure_pascals"), 100000.0, 2.0));
+    assert!(near(
+        sample(&body, "meter_raw_pressure_pascals"),
+        100000.0,
+        2.0
+    ));
+    // Corrected humidity cannot exceed 100%.
+    assert_eq!(sample(&body, "meter_humidity_percent"), Some(100.0));
+    assert!(near(sample(&body, "meter_raw_humidity_percent"), 98.0, 0.1));
+    assert_eq!(
+        sample(
+            &body,
+            r#"bme280_correction_info{sensor="hvac",quantity="temperature",gain="1",offset="-1.5"}"#
+        ),
+        Some(1.0)
+    );
+    assert_eq!(
+        sample(
+            &body,
+            r#"bme280_correction_info{quantity="pressure",gain="1",offset="0"}"#
+        ),
+        Some(1.0)
+    );
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use common::{chip, near, sample, scrape_until, serve, test_config};
+use prometheus_bme280_exporter::config::Config;
+use prometheus_bme280_exporter::derived::{self, Magnus};
+use prometheus_bme280_exporter::sensor::Reading;
+
+#[test]
+fn dew_point() {
+    let magnus = Magnus::default();
+
+    assert!(near(Some(magnus.dew_point(20.0, 50.0)), 9.26, 0.01));
+    assert!(near(Some(magnus.dew_point(-10.0, 80.0)), -12.80, 0.01));
+    // Saturated air is at its dew point.
+    assert!(near(Some(magnus.dew_point(15.0, 100.0)), 15.0, 1e-9));
+    assert!(magnus.dew_point(20.0, 0.0).is_nan());
+    assert!(magnus.dew_point(20.0, f64::NAN).is_nan());
+}
+
+#[test]
+fn dew_point_with_other_constants() {
+    // Alduchov and Eskridge (1996)
+    let magnus = Magnus {
+        a: 610.94,
+        b: 17.625,
+        c: 243.04,
+    };
+
+    assert!(near(Some(magnus.dew_point(20.0, 50.0)), 9.27, 0.01));
+}
+
+#[test]
+fn sea_level_pressure() {
+    // Standard atmosphere at 500m
+    assert!(near(
+        Some(derived::sea_level_pressure(95461.0, 11.75, 500.0)),
+        101325.0,
+        10.0
+    ));
+    assert!(near(
+        Some(derived::sea_level_pressure(100000.0, 20.0, 0.0)),
+        100000.0,
+        1e-6
+    ));
+    // Below sea level the pressure is reduced.
+    assert!(derived::sea_level_pressure(100000.0, 20.0, -100.0) < 100000.0);
+}
+
+#[test]
+fn altitude_is_inverse_of_sea_level_pressure() {
+    for altitude in [-400.0, 0.0, 350.0, 2500.0] {
+        let sea_level = derived::sea_level_pressure(90000.0, 5.0, altitude);
+        assert!(near(
+            Some(derived::altitude(90000.0, 5.0, sea_level)),
+            altitude,
+            1e-6
+        ));
+    }
+}
+
+#[tokio::test]
+async fn exports_derived_quantities() {
+    let at_pressure = |pressure| {
+        chip(Reading {
+            temperature: 10.0,
+            pressure,
+            humidity: 70.0,
+        })
+    };
+    let parsed = Config::parse(
+        r#"
+        [[sensor]]
+        name = "valley"
+        device = "emulated-valley"
+        sea_level_pressure = 101325.0
+
+        [[sensor]]
+        name = "hill"
+        device = "emulated-hill"
+        altitude = 500.0
+        "#,
+    )
+    .unwrap();
+    let config = Config {
+        sensors: parsed.sensors,
+        ..test_config()
+    };
+    let addr = serve(
+        vec![
+            ("valley", at_pressure(99000.0)),
+            ("hill", at_pressure(95000.0)),
+        ],
+        config,
+    )
+    .await;
+
+    let (_, body) = scrape_until(addr, |_, body| {
+        sample(body, r#"meter_temperature_celsius{sensor="valley"}"#).is_some()
+            && sample(body, r#"meter_temperature_celsius{sensor="hill"}"#).is_some()
+    })
+    .await;
+    let expected = derived::altitude(99000.0, 10.0, 101325.0);
+    assert!(near(
+        sample(&body, r#"meter_altitude_meters{sensor="valley"}"#),
+        expected,
+        0.5
+    ));
+    let expected = derived::sea_level_pressure(95000.0, 10.0, 500.0);
+    assert!(near(
+        sample(&body, r#"meter_sea_level_pressure_pascals{sensor="hill"}"#),
+        expected,
+        3.0
+    ));
+    // Only what can be calculated from the configuration is exported.
+    assert_eq!(
+        sample(
+            &body,
+            r#"meter_sea_level_pressure_pascals{sensor="valley"}"#
+        ),
+        None
+    );
+    assert_eq!(
+        sample(&body, r#"meter_altitude_meters{sensor="hill"}"#),
+        None
+    );
+}
+
+#[test]
+fn heat_index() {
+    // 90 °F at 70% is 106 °F in the NWS table.
+    assert!(near(Some(derived::heat_index(32.22, 70.0)), 41.07, 0.05));
+    // 104 °F at 10% is 98 °F.
+    assert!(near(Some(derived::heat_index(40.0, 10.0)), 36.7, 0.05));
+    assert!(derived::heat_index(25.0, 70.0).is_nan());
+}
+
+#[test]
+fn humidex() {
+    assert!(near(
+        Some(derived::humidex(&Magnus::default(), 30.0, 70.0)),
+        40.9,
+        0.1
+    ));
+    assert!(derived::humidex(&Magnus::default(), 15.0, 70.0).is_nan());
+}
+
+#[test]
+fn wet_bulb_temperature() {
+    // Stull's own example
+    assert!(near(
+        Some(derived::wet_bulb_temperature(20.0, 50.0)),
+        13.7,
+        0.05
+    ));
+    assert!(derived::wet_bulb_temperature(20.0, 100.0).is_nan());
+    assert!(derived::wet_bulb_temperature(-30.0, 50.0).is_nan());
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use hyper::StatusCode;
+
+use common::{chip, sample, scrape_until, start};
+use prometheus_bme280_exporter::cli::FailureResponse;
+use prometheus_bme280_exporter::sensor::Reading;
+
+#[tokio::test]
+async fn reports_sensor_down() {
+    let chip = chip(Reading {
+        temperature: 20.0,
+        pressure: 100000.0,
+        humidity: 50.0,
+    });
+    let addr = start(chip.clone(), FailureResponse::Down).await;
+
+    scrape_until(addr, |_, body| sample(body, "bme280_up") == Some(1.0)).await;
+
+    // The sensor is down as soon as a measurement fails, although its last
+    // reading is exported until it goes stale.
+    chip.set_connected(false);
+    let (_, body) = scrape_until(addr, |_, body| sample(body, "bme280_up") == Some(0.0)).await;
+    assert!(sample(&body, "meter_temperature_celsius").is_some());
+
+    let (status, body) = scrape_until(addr, |_, body| {
+        sample(body, "meter_temperature_celsius").is_none()
+    })
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(sample(&body, "bme280_up"), Some(0.0));
+    assert_eq!(sample(&body, "meter_temperature_celsius"), None);
+    assert_eq!(sample(&body, "meter_pressure_pascals"), None);
+    assert_eq!(sample(&body, "meter_humidity_percent"), None);
+}
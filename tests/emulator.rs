@@ -0,0 +1,252 @@
+use prometheus_bme280_exporter::cli::SensorAddress;
+use prometheus_bme280_exporter::driver::{
+    Calibration, Filter, Mode, Oversampling, Settings, Standby,
+};
+use prometheus_bme280_exporter::emulator::EmulatedBme280;
+use prometheus_bme280_exporter::sensor::{Bme280Sensor, ErrorKind, Reading, Sensor, SensorError};
+
+const ROOM: Reading = Reading {
+    temperature: 21.5,
+    pressure: 101325.0,
+    humidity: 45.0,
+};
+
+fn assert_close(actual: Reading, expected: Reading) {
+    assert!(
+        (actual.temperature - expected.temperature).abs() < 0.05
+            && (actual.pressure - expected.pressure).abs() < 2.0
+            && (actual.humidity - expected.humidity).abs() < 0.1,
+        "measured {:?}, expected {:?}",
+        actual,
+        expected
+    );
+}
+
+fn primary(reading: Reading) -> EmulatedBme280 {
+    EmulatedBme280::new(SensorAddress::Primary.value(), reading)
+}
+
+#[test]
+fn measures_emulated_conditions() {
+    let mut sensor = Bme280Sensor::new(primary(ROOM), SensorAddress::Primary).unwrap();
+
+    assert_close(sensor.measure().unwrap(), ROOM);
+}
+
+#[test]
+fn measures_across_operating_range() {
+    let chip = primary(ROOM);
+    let mut sensor = Bme280Sensor::new(chip.clone(), SensorAddress::Primary).unwrap();
+
+    for reading in [
+        Reading {
+            temperature: -30.0,
+            pressure: 50000.0,
+            humidity: 5.0,
+        },
+        Reading {
+            temperature: 0.0,
+            pressure: 90000.0,
+            humidity: 99.0,
+        },
+        Reading {
+            temperature: 60.0,
+            pressure: 105000.0,
+            humidity: 20.0,
+        },
+    ] {
+        chip.set_reading(reading);
+        assert_close(sensor.measure().unwrap(), reading);
+    }
+}
+
+/// Results worked out without the driver, for the emulator's default
+/// calibration. Temperature and pressure are the worked example of the
+/// BMP280 datasheet, whose trimming parameters these are; humidity comes
+/// from the 32 bit integer formula of the BME280 datasheet.
+#[test]
+fn compensates_reference_values() {
+    let chip = primary(ROOM);
+    let mut sensor = Bme280Sensor::new(chip.clone(), SensorAddress::Primary).unwrap();
+
+    for (adc_h, humidity) in [(24000, 21.464), (30000, 54.997), (34000, 77.170)] {
+        chip.set_raw(519888, 415148, adc_h);
+        let reading = sensor.measure().unwrap();
+        assert!((reading.temperature - 25.08).abs() < 0.01, "{:?}", reading);
+        assert!((reading.pressure - 100653.27).abs() < 0.05, "{:?}", reading);
+        assert!((reading.humidity - humidity).abs() < 0.01, "{:?}", reading);
+    }
+}
+
+#[test]
+fn uncompensatable_pressure_leaves_other_quantities() {
+    let calibration = Calibration {
+        dig_p1: 0,
+        ..Calibration::default()
+    };
+    let chip = EmulatedBme280::with_calibration(SensorAddress::Primary.value(), ROOM, calibration);
+    let mut sensor = Bme280Sensor::new(chip.clone(), SensorAddress::Primary).unwrap();
+
+    chip.set_raw(519888, 415148, 30000);
+    let reading = sensor.measure().unwrap();
+    assert!((reading.temperature - 25.08).abs() < 0.01, "{:?}", reading);
+    assert!(reading.pressure.is_nan(), "{:?}", reading);
+    assert!((reading.humidity - 54.997).abs() < 0.01, "{:?}", reading);
+}
+
+#[test]
+fn init_configures_sensor() {
+    let chip = primary(ROOM);
+    Bme280Sensor::new(chip.clone(), SensorAddress::Primary).unwrap();
+
+    // Humidity 1x, temperature 2x and pressure 16x oversampling in sleep mode,
+    // IIR filter coefficient 16.
+    assert_eq!(chip.register(0xF2), 0x01);
+    assert_eq!(chip.register(0xF4), 0x54);
+    assert_eq!(chip.register(0xF5), 0x10);
+}
+
+#[test]
+fn init_applies_settings() {
+    let chip = primary(ROOM);
+    let settings = Settings {
+        mode: Mode::Forced,
+        temperature_oversampling: Oversampling::X1,
+        pressure_oversampling: Oversampling::X8,
+        humidity_oversampling: Oversampling::X16,
+        filter: Filter::X4,
+        standby: Standby::Ms1000,
+    };
+    let mut sensor =
+        Bme280Sensor::with_settings(chip.clone(), SensorAddress::Primary, settings).unwrap();
+
+    assert_eq!(chip.register(0xF2), 0x05);
+    assert_eq!(chip.register(0xF4), 0x30);
+    assert_eq!(chip.register(0xF5), 0xA8);
+    assert_close(sensor.measure().unwrap(), ROOM);
+}
+
+#[test]
+fn skipped_quantities_are_not_measured() {
+    let settings = Settings {
+        pressure_oversampling: Oversampling::Skip,
+        humidity_oversampling: Oversampling::Skip,
+        ..Settings::default()
+    };
+    let mut sensor =
+        Bme280Sensor::with_settings(primary(ROOM), SensorAddress::Primary, settings).unwrap();
+
+    let reading = sensor.measure().unwrap();
+    assert!((reading.temperature - ROOM.temperature).abs() < 0.05);
+    assert!(reading.pressure.is_nan());
+    assert!(reading.humidity.is_nan());
+}
+
+#[test]
+fn measure_returns_to_sleep_mode() {
+    let chip = primary(ROOM);
+    let mut sensor = Bme280Sensor::new(chip.clone(), SensorAddress::Primary).unwrap();
+
+    sensor.measure().unwrap();
+
+    assert_eq!(chip.register(0xF4) & 0x03, 0x00);
+}
+
+#[test]
+fn forced_mode_notices_lost_settings() {
+    let chip = primary(ROOM);
+    let mut sensor = Bme280Sensor::new(chip.clone(), SensorAddress::Primary).unwrap();
+    assert_close(sensor.measure().unwrap(), ROOM);
+
+    // Without its settings the chip would skip humidity, whose reset value
+    // compensates to a plausible but wrong reading.
+    chip.set_connected(false);
+    chip.set_connected(true);
+    assert_eq!(sensor.measure().unwrap_err().kind(), ErrorKind::Other);
+}
+
+#[test]
+fn normal_mode_reads_latest_measurement() {
+    let chip = primary(ROOM);
+    let settings = Settings {
+        mode: Mode::Normal,
+        ..Settings::default()
+    };
+    let mut sensor =
+        Bme280Sensor::with_settings(chip.clone(), SensorAddress::Primary, settings).unwrap();
+
+    assert_eq!(chip.register(0xF4), 0x57);
+    assert_close(sensor.measure().unwrap(), ROOM);
+    let warm = Reading {
+        temperature: 30.0,
+        ..ROOM
+    };
+    chip.set_reading(warm);
+    assert_close(sensor.measure().unwrap(), warm);
+    assert_eq!(chip.register(0xF4) & 0x03, 0x03);
+
+    // A power cycle puts the chip to sleep, which must not go unnoticed.
+    chip.set_connected(false);
+    chip.set_connected(true);
+    assert_eq!(sensor.measure().unwrap_err().kind(), ErrorKind::Other);
+}
+
+#[test]
+fn init_fails_for_unsupported_chip() {
+    let chip = primary(ROOM);
+    chip.set_chip_id(0x55);
+
+    let err = Bme280Sensor::new(chip, SensorAddress::Primary)
+        .err()
+        .unwrap();
+    let err = err.downcast_ref::<SensorError>().unwrap();
+    assert_eq!(err.kind(), ErrorKind::UnsupportedChip);
+}
+
+#[test]
+fn bmp280_does_not_measure_humidity() {
+    let chip = primary(ROOM);
+    chip.set_chip_id(0x58);
+    let mut sensor = Bme280Sensor::new(chip.clone(), SensorAddress::Primary).unwrap();
+
+    let reading = sensor.measure().unwrap();
+    assert!((reading.temperature - ROOM.temperature).abs() < 0.05);
+    assert!((reading.pressure - ROOM.pressure).abs() < 2.0);
+    assert!(reading.humidity.is_nan());
+    // The BMP280 has no ctrl_hum register to write.
+    assert_eq!(chip.register(0xF2), 0x00);
+}
+
+#[test]
+fn init_fails_without_chip_at_address() {
+    let chip = primary(ROOM);
+
+    assert!(Bme280Sensor::new(chip, SensorAddress::Secondary).is_err());
+}
+
+#[test]
+fn init_fails_for_disconnected_chip() {
+    let chip = primary(ROOM);
+    chip.set_connected(false);
+
+    assert!(Bme280Sensor::new(chip, SensorAddress::Primary).is_err());
+}
+
+#[test]
+fn measure_fails_for_disconnected_chip() {
+    let chip = primary(ROOM);
+    let mut sensor = Bme280Sensor::new(chip.clone(), SensorAddress::Primary).unwrap();
+
+    chip.set_connected(false);
+    assert_eq!(sensor.measure().unwrap_err().kind(), ErrorKind::Nack);
+}
+
+#[test]
+fn measure_fails_on_bus_error() {
+    let chip = primary(ROOM);
+    let mut sensor = Bme280Sensor::new(chip.clone(), SensorAddress::Primary).unwrap();
+
+    chip.fail_next(1);
+    assert_eq!(sensor.measure().unwrap_err().kind(), ErrorKind::Bus);
+    assert_close(sensor.measure().unwrap(), ROOM);
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::StatusCode;
+use prometheus::proto::{MetricFamily, MetricType};
+use protobuf::CodedInputStream;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use common::{chip, get_with_headers, near, sample, scrape_until, start};
+use prometheus_bme280_exporter::cli::FailureResponse;
+use prometheus_bme280_exporter::sensor::Reading;
+
+const TEXT: &str = "text/plain; version=0.0.4; charset=utf-8";
+const OPENMETRICS: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const PROTOBUF: &str =
+    "application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; encoding=delimited";
+
+/// What Prometheus sends when it prefers OpenMetrics.
+const PROMETHEUS_ACCEPT: &str = "application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,text/plain;version=0.0.4;q=0.5,*/*;q=0.1";
+
+async fn scrape_bytes(addr: SocketAddr, accept: Option<&str>) -> (String, Vec<u8>) {
+    let headers: Vec<_> = accept
+        .map(|accept| ("Accept", accept))
+        .into_iter()
+        .collect();
+    let response = get_with_headers(addr, "/metrics", &headers).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let content_type = response.headers()[CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .to_string();
+    (content_type, response.into_body())
+}
+
+async fn scrape(addr: SocketAddr, accept: Option<&str>) -> (String, String) {
+    let (content_type, body) = scrape_bytes(addr, accept).await;
+    (content_type, String::from_utf8(body).unwrap())
+}
+
+/// Decodes a stream of length-delimited metric families.
+fn decode(body: &[u8]) -> Vec<MetricFamily> {
+    let mut input = CodedInputStream::from_bytes(body);
+    let mut families = Vec::new();
+    while !input.eof().unwrap() {
+        families.push(input.read_message().unwrap());
+    }
+    families
+}
+
+#[tokio::test]
+async fn negotiates_format() {
+    let chip = chip(Reading {
+        temperature: 23.25,
+        pressure: 98765.0,
+        humidity: 61.5,
+    });
+    let addr = start(chip, FailureResponse::Unavailable).await;
+    scrape_until(addr, |status, _| status == StatusCode::OK).await;
+
+    for accept in [
+        None,
+        Some("*/*"),
+        Some("text/plain"),
+        Some("application/json"),
+        // Only delimited protocol buffers are supported.
+        Some("application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; encoding=text"),
+    ] {
+        let (content_type, body) = scrape(addr, accept).await;
+        assert_eq!(content_type, TEXT, "{:?}", accept);
+        assert!(body.contains("# TYPE bme280_measurement_errors_total counter"));
+        assert!(!body.contains("# EOF"));
+    }
+    let (content_type, _) = scrape(
+        addr,
+        Some("application/openmetrics-text;q=0.5,text/plain;q=0.9"),
+    )
+    .await;
+    assert_eq!(content_type, TEXT);
+
+    // The same metric families as in the text format, in the same order.
+    let (_, text) = scrape(addr, None).await;
+    let (content_type, body) = scrape_bytes(addr, Some(PROTOBUF)).await;
+    assert_eq!(content_type, PROTOBUF);
+    let families = decode(&body);
+    let names: Vec<&str> = families.iter().map(|family| family.get_name()).collect();
+    let text_names: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("# TYPE "))
+        .map(|line| line.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(names, text_names);
+    let temperature = families
+        .iter()
+        .find(|family| family.get_name() == "meter_temperature_celsius")
+        .unwrap();
+    assert_eq!(temperature.get_field_type(), MetricType::GAUGE);
+    let metric = &temperature.get_metric()[0];
+    assert!(near(Some(metric.get_gauge().get_value()), 23.25, 0.05));
+    let labels: Vec<(&str, &str)> = metric
+        .get_label()
+        .iter()
+        .map(|label| (label.get_name(), label.get_value()))
+        .collect();
+    assert!(labels.contains(&("sensor", "bme280")), "{:?}", labels);
+    let errors = families
+        .iter()
+        .find(|family| family.get_name() == "bme280_measurement_errors_total")
+        .unwrap();
+    assert_eq!(errors.get_field_type(), MetricType::COUNTER);
+
+    let (content_type, body) = scrape(addr, Some(PROMETHEUS_ACCEPT)).await;
+    assert_eq!(content_type, OPENMETRICS);
+    assert!(body.ends_with("# EOF\n"), "{}", body);
+    assert!(body.contains("# TYPE meter_temperature_celsius gauge\n"));
+    assert!(body.contains("# UNIT meter_temperature_celsius celsius\n"));
+    assert!(body.contains("# UNIT bme280_measurement_duration_seconds seconds\n"));
+    assert!(!body.contains("# UNIT bme280_up"));
+    assert!(body.contains("# TYPE bme280_measurement_errors counter\n"));
+    assert!(body.contains("# TYPE bme280_settings info\n"));
+    assert!(sample(&body, "meter_temperature_celsius").is_some());
+    assert_eq!(sample(&body, "bme280_settings_info"), Some(1.0));
+    assert_eq!(
+        sample(&body, r#"bme280_measurement_errors_total{kind="nack"}"#),
+        Some(0.0)
+    );
+    assert!(body.contains(r#"bme280_measurement_duration_seconds_bucket{address="0x76",bus="emulated",location="",sensor="bme280",le="+Inf"}"#));
+
+    // Counters and histograms were created when the sensor was first
+    // sampled, shortly before this.
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64();
+    for name in [
+        "bme280_measurement_errors_created",
+        "bme280_measurement_duration_seconds_created",
+        "bme280_reinitialisations_created",
+    ] {
+        let created = sample(&body, name).unwrap_or_else(|| panic!("no {}", name));
+        assert!(
+            now - 10.0 < created && created <= now,
+            "{}: {}",
+            name,
+            created
+        );
+    }
+}
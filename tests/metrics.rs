@@ -0,0 +1,80 @@
+mod common;
+
+use hyper::StatusCode;
+
+use common::{chip, get, near, sample, scrape_until, start};
+use prometheus_bme280_exporter::cli::FailureResponse;
+use prometheus_bme280_exporter::sensor::Reading;
+
+// The gauges are process-wide, so all scrapes happen in a single test.
+#[tokio::test]
+async fn scrapes_emulated_sensor() {
+    let chip = chip(Reading {
+        temperature: 23.25,
+        pressure: 98765.0,
+        humidity: 61.5,
+    });
+    let addr = start(chip.clone(), FailureResponse::Unavailable).await;
+
+    let (status, body) = scrape_until(addr, |status, _| status == StatusCode::OK).await;
+    assert!(near(
+        sample(&body, "meter_temperature_celsius"),
+        23.25,
+        0.05
+    ));
+    assert!(near(sample(&body, "meter_pressure_pascals"), 98765.0, 2.0));
+    assert!(near(sample(&body, "meter_humidity_percent"), 61.5, 0.1));
+    assert!(near(sample(&body, "meter_dew_point_celsius"), 15.43, 0.05));
+    assert_eq!(sample(&body, "bme280_up"), Some(1.0));
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        sample(
+            &body,
+            "bme280_last_successful_measurement_timestamp_seconds"
+        )
+        .unwrap()
+            > 0.0
+    );
+    assert!(sample(&body, "bme280_measurement_duration_seconds_count").unwrap() >= 1.0);
+    assert_eq!(
+        sample(&body, "bme280_measurement_errors_total{kind=\"nack\"}"),
+        Some(0.0)
+    );
+    assert_eq!(sample(&body, "bme280_reinitialisations_total"), Some(0.0));
+    assert_eq!(
+        sample(
+            &body,
+            r#"bme280_settings_info{mode="forced",temperature_oversampling="2x",pressure_oversampling="16x",humidity_oversampling="1x",filter="16",standby="0.5ms"}"#
+        ),
+        Some(1.0)
+    );
+
+    chip.set_reading(Reading {
+        temperature: -5.0,
+        pressure: 101000.0,
+        humidity: 80.0,
+    });
+    scrape_until(addr, |_, body| {
+        near(sample(body, "meter_temperature_celsius"), -5.0, 0.05)
+    })
+    .await;
+
+    // Once the sensor stops responding and the reading goes stale, scrapes
+    // fail with the reason.
+    chip.set_connected(false);
+    let (_, body) = scrape_until(addr, |status, _| status == StatusCode::SERVICE_UNAVAILABLE).await;
+    assert!(body.contains("sensor unavailable"), "{}", body);
+    assert!(body.contains("NoAcknowledge"), "{}", body);
+
+    chip.set_connected(true);
+    let (_, body) = scrape_until(addr, |status, _| status == StatusCode::OK).await;
+    assert!(sample(&body, "bme280_measurement_errors_total{kind=\"nack\"}").unwrap() >= 1.0);
+    assert_eq!(
+        sample(&body, "bme280_measurement_errors_total{kind=\"bus\"}"),
+        Some(0.0)
+    );
+    assert!(sample(&body, "bme280_reinitialisations_total").unwrap() >= 1.0);
+
+    let (status, _) = get(addr, "/").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{chip, near, sample, scrape_until, serve, test_config};
+use prometheus_bme280_exporter::cli::FailureResponse;
+use prometheus_bme280_exporter::config::Config;
+use prometheus_bme280_exporter::correction::Corrections;
+use prometheus_bme280_exporter::processing::{
+    Limit, Limits, Processor, Reason, Rejection, Smoothing,
+};
+use prometheus_bme280_exporter::sensor::Reading;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+fn reading(temperature: f64) -> Reading {
+    Reading {
+        temperature,
+        pressure: 100000.0,
+        humidity: 50.0,
+    }
+}
+
+fn temperatures(processor: &mut Processor, values: &[f64]) -> Vec<Option<f64>> {
+    let start = Instant::now();
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| {
+            processor
+                .process(&reading(value), start + MINUTE * i as u32)
+                .ok()
+                .map(|reading| reading.temperature)
+        })
+        .collect()
+}
+
+#[test]
+fn median_removes_spikes() {
+    let smoothing = Smoothing {
+        median: Some(3),
+        ema: None,
+    };
+    let mut processor = Processor::new(Corrections::default(), Limits::default(), smoothing);
+    assert_eq!(
+        temperatures(&mut processor, &[20.0, 40.0, 21.0, 22.0, 23.0]),
+        [Some(20.0), Some(30.0), Some(21.0), Some(22.0), Some(22.0)]
+    );
+}
+
+#[test]
+fn moving_average_follows_median() {
+    let smoothing = Smoothing {
+        median: None,
+        ema: Some(0.5),
+    };
+    let mut processor = Processor::new(Corrections::default(), Limits::default(), smoothing);
+    assert_eq!(
+        temperatures(&mut processor, &[20.0, 22.0, 22.0, 30.0]),
+        [Some(20.0), Some(21.0), Some(21.5), Some(25.75)]
+    );
+}
+
+#[test]
+fn rejects_implausible_readings() {
+    let limits = Limits {
+        temperature: Limit {
+            min: Some(-40.0),
+            max: Some(85.0),
+            max_change_per_minute: Some(2.0),
+        },
+        ..Limits::default()
+    };
+    let mut processor = Processor::new(Corrections::default(), limits, Smoothing::default());
+    // Rejected readings are not compared against, so the rate of change is
+    // measured from the last accepted one.
+    assert_eq!(
+        temperatures(&mut processor, &[20.0, 90.0, 21.0, 30.0, 24.5]),
+        [Some(20.0), None, Some(21.0), None, Some(24.5)]
+    );
+
+    let start = Instant::now();
+    let mut processor = Processor::new(Corrections::default(), limits, Smoothing::default());
+    processor.process(&reading(20.0), start).unwrap();
+    assert_eq!(
+        processor.process(&reading(-50.0), start + MINUTE),
+        Err(Rejection {
+            quantity: "temperature",
+            reason: Reason::OutOfRange,
+            value: -50.0,
+        })
+    );
+    assert_eq!(
+        processor
+            .process(&reading(25.0), start + MINUTE)
+            .unwrap_err()
+            .reason,
+        Reason::RateOfChange
+    );
+}
+
+#[tokio::test]
+async fn counts_rejected_readings() {
+    let chip = chip(Reading {
+        temperature: 24.0,
+        pressure: 100000.0,
+        humidity: 40.0,
+    });
+    let parsed = Config::parse(
+        r#"
+        [[sensor]]
+        name = "attic"
+        device = "emulated"
+
+        [sensor.limits]
+        humidity = { max = 30.0 }
+        "#,
+    )
+    .unwrap();
+    // Until a reading is accepted there is nothing to export.
+    let config = Config {
+        on_failure: FailureResponse::Down,
+        sensors: parsed.sensors,
+        ..test_config()
+    };
+    let addr = serve(vec![("attic", chip.clone())], config).await;
+
+    let (_, body) = scrape_until(addr, |_, body| {
+        sample(
+            body,
+            r#"bme280_rejected_samples_total{sensor="attic",quantity="humidity",reason="out_of_range"}"#,
+        )
+        .is_some_and(|count| count > 0.0)
+    })
+    .await;
+    assert_eq!(sample(&body, "meter_humidity_percent"), None);
+    assert_eq!(
+        sample(
+            &body,
+            r#"bme280_rejected_samples_total{quantity="temperature",reason="rate_of_change"}"#
+        ),
+        Some(0.0)
+    );
+
+    chip.set_reading(Reading {
+        humidity: 25.0,
+        ..reading(24.0)
+    });
+    let (_, body) = scrape_until(addr, |_, body| {
+        sample(body, "meter_humidity_percent").is_some()
+    })
+    .await;
+    assert!(near(sample(&body, "meter_humidity_percent"), 25.0, 0.1));
+}
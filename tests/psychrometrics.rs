@@ -0,0 +1,95 @@
+mod common;
+
+use common::{chip, near, sample, scrape_until, serve, test_config};
+use prometheus_bme280_exporter::config::Config;
+use prometheus_bme280_exporter::derived::Magnus;
+use prometheus_bme280_exporter::psychrometrics::{Psychrometrics, Quantity};
+use prometheus_bme280_exporter::sensor::Reading;
+
+const WARM: Reading = Reading {
+    temperature: 25.0,
+    pressure: 101325.0,
+    humidity: 50.0,
+};
+
+#[test]
+fn calculates_quantities() {
+    let psychrometrics = Psychrometrics::new(&Magnus::default(), &WARM);
+
+    assert!(near(
+        Some(psychrometrics.saturation_vapor_pressure),
+        3160.06,
+        0.01
+    ));
+    assert!(near(Some(psychrometrics.vapor_pressure), 1580.03, 0.01));
+    assert!(near(
+        Some(psychrometrics.vapor_pressure_deficit),
+        1.58,
+        0.001
+    ));
+    assert!(near(Some(psychrometrics.absolute_humidity), 11.483, 0.001));
+    assert!(near(Some(psychrometrics.mixing_ratio), 9.853, 0.001));
+    assert!(near(Some(psychrometrics.specific_humidity), 9.757, 0.001));
+}
+
+#[test]
+fn saturated_air_has_no_deficit() {
+    let psychrometrics = Psychrometrics::new(
+        &Magnus::default(),
+        &Reading {
+            humidity: 100.0,
+            ..WARM
+        },
+    );
+
+    assert!(near(Some(psychrometrics.vapor_pressure_deficit), 0.0, 1e-9));
+    assert!(near(
+        Some(psychrometrics.vapor_pressure),
+        psychrometrics.saturation_vapor_pressure,
+        1e-9
+    ));
+}
+
+#[test]
+fn unmeasured_quantities_are_nan() {
+    let psychrometrics = Psychrometrics::new(
+        &Magnus::default(),
+        &Reading {
+            pressure: f64::NAN,
+            humidity: f64::NAN,
+            ..WARM
+        },
+    );
+
+    assert!(!psychrometrics.saturation_vapor_pressure.is_nan());
+    assert!(psychrometrics.vapor_pressure_deficit.is_nan());
+    assert!(psychrometrics.absolute_humidity.is_nan());
+    assert!(psychrometrics.mixing_ratio.is_nan());
+}
+
+#[tokio::test]
+async fn exports_enabled_quantities() {
+    let chip = chip(WARM);
+    let config = Config {
+        psychrometrics: vec![Quantity::VaporPressureDeficit, Quantity::AbsoluteHumidity],
+        ..test_config()
+    };
+    let addr = serve(vec![("greenhouse", chip)], config).await;
+
+    let (_, body) = scrape_until(addr, |_, body| {
+        sample(body, "meter_temperature_celsius").is_some()
+    })
+    .await;
+    assert!(near(
+        sample(&body, "meter_vapor_pressure_deficit_kilopascals"),
+        1.58,
+        0.005
+    ));
+    assert!(near(
+        sample(&body, "meter_absolute_humidity_grams_per_cubic_meter"),
+        11.48,
+        0.05
+    ));
+    assert_eq!(sample(&body, "meter_vapor_pressure_pascals"), None);
+    assert_eq!(sample(&body, "meter_mixing_ratio_grams_per_kilogram"), None);
+}
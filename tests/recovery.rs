@@ -0,0 +1,111 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{chip, near};
+use prometheus_bme280_exporter::cli::SensorAddress;
+use prometheus_bme280_exporter::emulator::EmulatedBme280;
+use prometheus_bme280_exporter::recovery::{RecoveringSensor, RecoveryPolicy};
+use prometheus_bme280_exporter::sensor::{Bme280Sensor, ErrorKind, Reading, Sensor, SensorLabels};
+
+const ROOM: Reading = Reading {
+    temperature: 21.5,
+    pressure: 101325.0,
+    humidity: 45.0,
+};
+
+fn recovering(chip: &EmulatedBme280, policy: RecoveryPolicy) -> impl Sensor {
+    let labels = SensorLabels::new("recovery", "emulated", SensorAddress::Primary.value(), "");
+    let chip = chip.clone();
+    RecoveringSensor::new(
+        move || Bme280Sensor::new(chip.clone(), SensorAddress::Primary),
+        policy,
+        &labels,
+    )
+}
+
+fn policy(failures: u32, backoff: Duration) -> RecoveryPolicy {
+    RecoveryPolicy {
+        failures,
+        initial_backoff: backoff,
+        max_backoff: backoff * 4,
+    }
+}
+
+#[test]
+fn starts_without_sensor() {
+    let chip = chip(ROOM);
+    chip.set_connected(false);
+    let mut sensor = recovering(&chip, policy(1, Duration::ZERO));
+
+    assert_eq!(sensor.measure().unwrap_err().kind(), ErrorKind::Nack);
+
+    chip.set_connected(true);
+    assert!(sensor.measure().is_ok());
+}
+
+#[test]
+fn reinitialises_after_consecutive_failures() {
+    let chip = chip(ROOM);
+    let mut sensor = recovering(&chip, policy(2, Duration::ZERO));
+    sensor.measure().unwrap();
+
+    // Power cycling the chip loses its configuration, which only init
+    // restores.
+    chip.set_connected(false);
+    assert!(sensor.measure().is_err());
+    chip.set_connected(true);
+    assert_eq!(chip.register(0xF5), 0x00);
+
+    chip.fail_next(1);
+    assert!(sensor.measure().is_err());
+    sensor.measure().unwrap();
+    assert_eq!(chip.register(0xF5), 0x10);
+}
+
+#[test]
+fn keeps_sensor_open_below_failure_threshold() {
+    let chip = chip(ROOM);
+    let mut sensor = recovering(&chip, policy(3, Duration::ZERO));
+    sensor.measure().unwrap();
+
+    chip.set_connected(false);
+    assert!(sensor.measure().is_err());
+    chip.set_connected(true);
+    chip.fail_next(1);
+    assert!(sensor.measure().is_err());
+    assert_eq!(chip.register(0xF5), 0x00);
+
+    // Having lost its settings the chip cannot be trusted, which takes the
+    // third failure to put right.
+    let err = sensor.measure().unwrap_err();
+    assert!(err.to_string().contains("SettingsLost"), "{}", err);
+    let reading = sensor.measure().unwrap();
+    assert!(
+        near(Some(reading.humidity), ROOM.humidity, 0.1),
+        "{:?}",
+        reading
+    );
+    assert!(
+        near(Some(reading.pressure), ROOM.pressure, 2.0),
+        "{:?}",
+        reading
+    );
+    assert_eq!(chip.register(0xF5), 0x10);
+}
+
+#[test]
+fn backs_off_between_attempts_to_open() {
+    let chip = chip(ROOM);
+    chip.set_connected(false);
+    let mut sensor = recovering(&chip, policy(1, Duration::from_secs(60)));
+
+    assert!(sensor.measure().is_err());
+    let transactions = chip.transactions();
+
+    chip.set_connected(true);
+    let err = sensor.measure().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Nack);
+    assert!(err.to_string().contains("retrying in"), "{}", err);
+    assert_eq!(chip.transactions(), transactions);
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use hyper::StatusCode;
+use std::fs;
+
+use common::{chip, get, post, sample, scrape_until, start_from_file, start_sensors};
+use prometheus_bme280_exporter::cli::FailureResponse;
+use prometheus_bme280_exporter::emulator::EmulatedBme280;
+use prometheus_bme280_exporter::sensor::Reading;
+
+const SETTINGS: &str = r#"
+sample_interval = "50ms"
+max_staleness = "300ms"
+
+[recovery]
+reinit_after = 1
+backoff = "50ms"
+max_backoff = "50ms"
+"#;
+
+const INDOOR: &str = r#"
+[[sensor]]
+name = "indoor"
+device = "emulated-indoor"
+"#;
+
+const OUTDOOR: &str = r#"
+[[sensor]]
+name = "outdoor"
+device = "emulated-outdoor"
+"#;
+
+fn at_temperature(temperature: f64) -> EmulatedBme280 {
+    chip(Reading {
+        temperature,
+        pressure: 100000.0,
+        humidity: 50.0,
+    })
+}
+
+#[tokio::test]
+async fn reloads_configuration() {
+    let path = std::env::temp_dir().join(format!("bme280-reload-{}.toml", std::process::id()));
+    fs::write(&path, [SETTINGS, INDOOR].concat()).unwrap();
+    let addr = start_from_file(
+        &path,
+        vec![
+            ("indoor", at_temperature(21.0)),
+            ("outdoor", at_temperature(5.0)),
+        ],
+    )
+    .await;
+
+    let (_, body) = scrape_until(addr, |_, body| {
+        sample(body, "bme280_up{sensor=\"indoor\"}") == Some(1.0)
+    })
+    .await;
+    assert_eq!(sample(&body, "bme280_up{sensor=\"outdoor\"}"), None);
+    assert_eq!(
+        sample(&body, "bme280_config_last_reload_successful"),
+        Some(1.0)
+    );
+
+    // Without a configuration file there is nothing to reload, which is not
+    // a failed reload.
+    let unconfigured =
+        start_sensors(vec![("fixed", at_temperature(15.0))], FailureResponse::Down).await;
+    let (status, body) = post(unconfigured, "/-/reload").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(
+        body.contains("not started from a configuration file"),
+        "{}",
+        body
+    );
+    let (_, body) = get(unconfigured, "/metrics").await;
+    assert_eq!(
+        sample(&body, "bme280_config_last_reload_successful"),
+        Some(1.0)
+    );
+
+    // Add a sensor and a label.
+    fs::write(
+        &path,
+        [SETTINGS, "[labels]\nsite = \"cabin\"\n", INDOOR, OUTDOOR].concat(),
+    )
+    .unwrap();
+    let (status, _) = post(addr, "/-/reload").await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = scrape_until(addr, |_, body| {
+        sample(body, "bme280_up{sensor=\"outdoor\"}") == Some(1.0)
+    })
+    .await;
+    assert_eq!(
+        sample(&body, "bme280_up{sensor=\"indoor\",site=\"cabin\"}"),
+        Some(1.0)
+    );
+
+    // Remove a sensor, whose metrics go away with it, and change the
+    // settings and correction of the other.
+    fs::write(
+        &path,
+        [
+            SETTINGS,
+            OUTDOOR,
+            "filter = \"off\"\n",
+            "[sensor.correction]\ntemperature = { offset = -1.5 }\n",
+        ]
+        .concat(),
+    )
+    .unwrap();
+    let (status, _) = post(addr, "/-/reload").await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = scrape_until(addr, |_, body| {
+        sample(body, "bme280_up{sensor=\"outdoor\"}") == Some(1.0)
+    })
+    .await;
+    assert!(!body.contains("sensor=\"indoor\""), "{}", body);
+    assert_eq!(
+        sample(
+            &body,
+            "bme280_settings_info{sensor=\"outdoor\",filter=\"off\"}"
+        ),
+        Some(1.0)
+    );
+    assert_eq!(
+        sample(
+            &body,
+            "bme280_settings_info{sensor=\"outdoor\",filter=\"16\"}"
+        ),
+        None
+    );
+    assert_eq!(
+        sample(
+            &body,
+            "bme280_correction_info{sensor=\"outdoor\",quantity=\"temperature\",offset=\"-1.5\"}"
+        ),
+        Some(1.0)
+    );
+    assert_eq!(
+        sample(
+            &body,
+            "bme280_correction_info{sensor=\"outdoor\",quantity=\"temperature\",offset=\"0\"}"
+        ),
+        None
+    );
+
+    // An invalid file leaves the configuration as it was.
+    fs::write(&path, [SETTINGS, OUTDOOR, "[[sensor]]\n"].concat()).unwrap();
+    let (status, body) = post(addr, "/-/reload").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(body.contains("missing field `name`"), "{}", body);
+    let (_, body) = get(addr, "/metrics").await;
+    assert_eq!(
+        sample(&body, "bme280_config_last_reload_successful"),
+        Some(0.0)
+    );
+    assert_eq!(sample(&body, "bme280_up{sensor=\"outdoor\"}"), Some(1.0));
+
+    let (status, _) = get(addr, "/-/reload").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+
+    fs::remove_file(&path).unwrap();
+}
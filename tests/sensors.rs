@@ -0,0 +1,82 @@
+mod common;
+
+use hyper::StatusCode;
+
+use common::{chip, near, sample, scrape_until, start_sensors};
+use prometheus_bme280_exporter::cli::FailureResponse;
+use prometheus_bme280_exporter::sensor::Reading;
+
+#[tokio::test]
+async fn isolates_failing_sensor() {
+    let indoor = chip(Reading {
+        temperature: 21.0,
+        pressure: 100000.0,
+        humidity: 40.0,
+    });
+    let outdoor = chip(Reading {
+        temperature: 4.0,
+        pressure: 100100.0,
+        humidity: 85.0,
+    });
+    let addr = start_sensors(
+        vec![("indoor", indoor.clone()), ("outdoor", outdoor.clone())],
+        FailureResponse::Unavailable,
+    )
+    .await;
+
+    let (_, body) = scrape_until(addr, |_, body| {
+        sample(body, "bme280_up{sensor=\"indoor\"}") == Some(1.0)
+            && sample(body, "bme280_up{sensor=\"outdoor\"}") == Some(1.0)
+    })
+    .await;
+    assert!(near(
+        sample(&body, "meter_temperature_celsius{sensor=\"indoor\"}"),
+        21.0,
+        0.05
+    ));
+    assert!(near(
+        sample(&body, "meter_temperature_celsius{sensor=\"outdoor\"}"),
+        4.0,
+        0.05
+    ));
+    assert!(body.contains(r#"address="0x76",bus="emulated",location="",sensor="outdoor""#));
+
+    outdoor.set_connected(false);
+    let (status, body) = scrape_until(addr, |_, body| {
+        sample(body, "bme280_up{sensor=\"outdoor\"}") == Some(0.0)
+            && sample(body, "meter_humidity_percent{sensor=\"outdoor\"}").is_none()
+    })
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(sample(&body, "bme280_up{sensor=\"indoor\"}"), Some(1.0));
+    assert!(near(
+        sample(&body, "meter_humidity_percent{sensor=\"indoor\"}"),
+        40.0,
+        0.1
+    ));
+    assert_eq!(
+        sample(&body, "meter_humidity_percent{sensor=\"outdoor\"}"),
+        None
+    );
+    assert!(
+        sample(
+            &body,
+            "bme280_measurement_errors_total{kind=\"nack\",sensor=\"outdoor\"}"
+        )
+        .unwrap()
+            >= 1.0
+    );
+    assert_eq!(
+        sample(
+            &body,
+            "bme280_measurement_errors_total{kind=\"nack\",sensor=\"indoor\"}"
+        ),
+        Some(0.0)
+    );
+
+    // Only once every sensor has failed does the scrape fail.
+    indoor.set_connected(false);
+    let (_, body) = scrape_until(addr, |status, _| status == StatusCode::SERVICE_UNAVAILABLE).await;
+    assert!(body.contains("indoor: sensor unavailable"), "{}", body);
+    assert!(body.contains("outdoor: sensor unavailable"), "{}", body);
+}
@@ -0,0 +1,142 @@
+use std::fs;
+
+use prometheus_bme280_exporter::sensor::{ErrorKind, Reading, Sensor};
+use prometheus_bme280_exporter::sim::{SimulatedSensor, Step};
+
+const ROOM: Reading = Reading {
+    temperature: 21.5,
+    pressure: 101325.0,
+    humidity: 45.0,
+};
+
+/// Loads a script with `contents` from a file unique to `name`.
+fn load(name: &str, contents: &str) -> anyhow::Result<SimulatedSensor> {
+    let path = std::env::temp_dir().join(format!("bme280-sim-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    let sensor = SimulatedSensor::load_script(&path);
+    fs::remove_file(&path).unwrap();
+    sensor
+}
+
+fn error(name: &str, contents: &str) -> String {
+    format!("{:#}", load(name, contents).err().unwrap())
+}
+
+#[test]
+fn constant_reports_same_reading() {
+    let mut sensor = SimulatedSensor::constant(ROOM);
+
+    for _ in 0..3 {
+        assert_eq!(sensor.measure().unwrap(), ROOM);
+    }
+}
+
+#[test]
+fn random_walk_drifts_within_operating_range() {
+    for start in [
+        ROOM,
+        Reading {
+            temperature: -40.0,
+            pressure: 30000.0,
+            humidity: 0.0,
+        },
+        Reading {
+            temperature: 85.0,
+            pressure: 110000.0,
+            humidity: 100.0,
+        },
+    ] {
+        let mut sensor = SimulatedSensor::random_walk(start);
+        let mut previous = start;
+        // Steps are at most 0.05°C, 5 Pa and 0.2 %, give or take rounding.
+        for _ in 0..1000 {
+            let reading = sensor.measure().unwrap();
+            assert!(
+                (-40.0..=85.0).contains(&reading.temperature),
+                "{:?}",
+                reading
+            );
+            assert!(
+                (30000.0..=110000.0).contains(&reading.pressure),
+                "{:?}",
+                reading
+            );
+            assert!((0.0..=100.0).contains(&reading.humidity), "{:?}", reading);
+            assert!((reading.temperature - previous.temperature).abs() <= 0.05 + 1e-9);
+            assert!((reading.pressure - previous.pressure).abs() <= 5.0 + 1e-6);
+            assert!((reading.humidity - previous.humidity).abs() <= 0.2 + 1e-9);
+            previous = reading;
+        }
+    }
+}
+
+#[test]
+fn script_replays_steps_and_starts_over() {
+    let mut sensor = load(
+        "replay",
+        "# warming up\n\
+         20 100000 40\n\
+         \n\
+         error bus unplugged\n\
+         \t21.5  100100.5  41 \n\
+         error\n",
+    )
+    .unwrap();
+
+    for _ in 0..2 {
+        assert_eq!(
+            sensor.measure().unwrap(),
+            Reading {
+                temperature: 20.0,
+                pressure: 100000.0,
+                humidity: 40.0,
+            }
+        );
+        let err = sensor.measure().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Other);
+        assert_eq!(err.to_string(), "simulated failure: bus unplugged");
+        assert_eq!(
+            sensor.measure().unwrap(),
+            Reading {
+                temperature: 21.5,
+                pressure: 100100.5,
+                humidity: 41.0,
+            }
+        );
+        assert!(sensor.measure().is_err());
+    }
+}
+
+#[test]
+fn clones_continue_from_the_same_step() {
+    let mut sensor =
+        SimulatedSensor::script(vec![Step::Reading(ROOM), Step::Failure("gone".to_string())])
+            .unwrap();
+    sensor.measure().unwrap();
+
+    let mut clone = sensor.clone();
+    assert!(clone.measure().is_err());
+    assert!(sensor.measure().is_err());
+}
+
+#[test]
+fn rejects_malformed_scripts() {
+    let message = error("values", "20 100000 40\n20 100000\n");
+    assert!(message.contains("line 2"), "{}", message);
+    assert!(message.contains("expected '<temperature> <pressure> <humidity>'"));
+
+    let message = error("number", "20 warm 40\n");
+    assert!(message.contains("line 1"), "{}", message);
+    assert!(message.contains("'warm' is not a number"), "{}", message);
+
+    let message = error("empty", "# nothing but comments\n\n");
+    assert!(message.contains("no steps"), "{}", message);
+
+    assert!(SimulatedSensor::script(Vec::new()).is_err());
+    let missing = std::env::temp_dir().join("bme280-sim-missing");
+    let message = format!(
+        "{:#}",
+        SimulatedSensor::load_script(&missing).err().unwrap()
+    );
+    assert!(message.contains("unable to read"), "{}", message);
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use hyper::body::HttpBody;
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Client, Response, StatusCode};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use common::{chip, near, post, scrape_until, serve, test_config};
+use prometheus_bme280_exporter::config::Config;
+use prometheus_bme280_exporter::sensor::Reading;
+
+async fn open(addr: SocketAddr) -> Response<Body> {
+    let uri = format!("http://{}/api/v1/stream", addr).parse().unwrap();
+    Client::new().get(uri).await.unwrap()
+}
+
+/// The data of each event received on `body` until `deadline`.
+async fn receive(body: &mut Body, deadline: Instant) -> Vec<Value> {
+    let mut text = String::new();
+    while let Ok(Some(chunk)) = tokio::time::timeout_at(deadline, body.data()).await {
+        text.push_str(std::str::from_utf8(&chunk.unwrap()).unwrap());
+    }
+    text.split("\n\n")
+        .filter(|event| !event.is_empty())
+        .map(|event| {
+            let mut lines = event.lines();
+            assert_eq!(lines.next(), Some("event: reading"), "{:?}", event);
+            let data = lines.next().unwrap().strip_prefix("data: ").unwrap();
+            serde_json::from_str(data).unwrap()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn streams_new_samples() {
+    let reading = Reading {
+        temperature: 21.5,
+        pressure: 100500.0,
+        humidity: 40.0,
+    };
+    let indoor = chip(reading);
+    let outdoor = chip(reading);
+    let config = Config {
+        stream_min_interval: Duration::from_millis(200),
+        ..test_config()
+    };
+    let addr = serve(vec![("indoor", indoor), ("outdoor", outdoor)], config).await;
+    scrape_until(addr, |status, _| status == StatusCode::OK).await;
+
+    let mut response = open(addr).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/event-stream");
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+    let events = receive(response.body_mut(), Instant::now() + Duration::from_secs(1)).await;
+
+    // The current readings first, then new samples of each sensor at most
+    // every 200ms although they are taken every 50ms.
+    assert_eq!(events[0]["name"], "indoor");
+    assert_eq!(events[1]["name"], "outdoor");
+    for name in ["indoor", "outdoor"] {
+        let events: Vec<_> = events
+            .iter()
+            .filter(|event| event["name"] == name)
+            .collect();
+        assert!((3..=6).contains(&events.len()), "{}: {:?}", name, events);
+        let timestamps: Vec<f64> = events
+            .iter()
+            .map(|event| event["timestamp"].as_f64().unwrap())
+            .collect();
+        assert!(
+            timestamps.windows(2).all(|pair| pair[1] - pair[0] >= 0.15),
+            "{}: {:?}",
+            name,
+            timestamps
+        );
+        let temperature = events[0]["readings"]["temperature_celsius"].as_f64();
+        assert!(near(temperature, 21.5, 0.05), "{:?}", temperature);
+    }
+
+    // Clients may go away at any time without disturbing the others.
+    drop(response);
+    let mut response = open(addr).await;
+    let events = receive(
+        response.body_mut(),
+        Instant::now() + Duration::from_millis(100),
+    )
+    .await;
+    assert_eq!(events[0]["up"], true);
+
+    let (status, _) = post(addr, "/api/v1/stream").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+}
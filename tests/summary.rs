@@ -0,0 +1,147 @@
+mod common;
+
+use hyper::StatusCode;
+use std::time::{Duration, Instant};
+
+use common::{
+    chip, get, near, sample, scrape_until, serve, test_config, MAX_STALENESS, SAMPLE_INTERVAL,
+};
+use prometheus_bme280_exporter::cli::FailureResponse;
+use prometheus_bme280_exporter::config::{Config, Exporter};
+use prometheus_bme280_exporter::sensor::Reading;
+use prometheus_bme280_exporter::summary::{Recorder, Window};
+
+const SECOND: Duration = Duration::from_secs(1);
+
+fn reading(temperature: f64) -> Reading {
+    Reading {
+        temperature,
+        pressure: 100000.0,
+        humidity: f64::NAN,
+    }
+}
+
+#[test]
+fn summarizes_readings_since_scrape() {
+    let start = Instant::now();
+    let mut recorder = Recorder::new(Window::Scrape);
+    for (i, temperature) in [20.0, 24.0, 22.0, 18.0].into_iter().enumerate() {
+        recorder.push(start + SECOND * i as u32, reading(temperature));
+    }
+
+    let summary = recorder.summarize(start + 4 * SECOND);
+    assert_eq!(summary.temperature.count, 4);
+    assert_eq!(summary.temperature.min, 18.0);
+    assert_eq!(summary.temperature.max, 24.0);
+    assert_eq!(summary.temperature.mean(), 21.0);
+    assert_eq!(summary.pressure.mean(), 100000.0);
+    // Quantities which were not measured have no statistics.
+    assert_eq!(summary.humidity.count, 0);
+    assert!(summary.humidity.min.is_nan());
+    assert!(summary.humidity.mean().is_nan());
+
+    // Each scrape starts a new window.
+    let summary = recorder.summarize(start + 5 * SECOND);
+    assert_eq!(summary.temperature.count, 0);
+    recorder.push(start + 6 * SECOND, reading(30.0));
+    let summary = recorder.summarize(start + 7 * SECOND);
+    assert_eq!(summary.temperature.count, 1);
+    assert_eq!(summary.temperature.max, 30.0);
+}
+
+#[test]
+fn summarizes_readings_in_fixed_window() {
+    let start = Instant::now();
+    let mut recorder = Recorder::new(Window::Fixed(10 * SECOND));
+    for (i, temperature) in [20.0, 24.0, 22.0, 18.0].into_iter().enumerate() {
+        recorder.push(start + 5 * SECOND * i as u32, reading(temperature));
+    }
+
+    // Readings at 5, 10 and 15 seconds; scraping does not reset them.
+    for _ in 0..2 {
+        let summary = recorder.summarize(start + 15 * SECOND);
+        assert_eq!(summary.temperature.count, 3);
+        assert_eq!(summary.temperature.min, 18.0);
+        assert_eq!(summary.temperature.max, 24.0);
+    }
+    let summary = recorder.summarize(start + 30 * SECOND);
+    assert_eq!(summary.temperature.count, 0);
+}
+
+#[tokio::test]
+async fn exports_summary_since_scrape() {
+    let reading = |temperature| Reading {
+        humidity: 50.0,
+        ..reading(temperature)
+    };
+    let chip = chip(reading(20.0));
+    let config = Config {
+        exporters: vec![Exporter::Readings, Exporter::Summary],
+        ..test_config()
+    };
+    let addr = serve(vec![("bme280", chip.clone())], config).await;
+
+    scrape_until(addr, |_, body| {
+        sample(body, "meter_summary_temperature_mean_celsius").is_some()
+    })
+    .await;
+
+    // A spike between two scrapes still shows in the maximum.
+    chip.set_reading(reading(30.0));
+    tokio::time::sleep(SAMPLE_INTERVAL * 3).await;
+    chip.set_reading(reading(20.0));
+    tokio::time::sleep(SAMPLE_INTERVAL * 3).await;
+
+    let (_, body) = get(addr, "/metrics").await;
+    assert!(near(sample(&body, "meter_temperature_celsius"), 20.0, 0.05));
+    assert!(near(
+        sample(&body, "meter_summary_temperature_max_celsius"),
+        30.0,
+        0.05
+    ));
+    assert!(near(
+        sample(&body, "meter_summary_temperature_min_celsius"),
+        20.0,
+        0.05
+    ));
+    let count = sample(&body, r#"meter_summary_samples{quantity="temperature"}"#).unwrap();
+    assert!(count >= 4.0, "{}", count);
+    assert!(near(
+        sample(&body, "meter_summary_humidity_mean_percent"),
+        50.0,
+        0.1
+    ));
+}
+
+#[tokio::test]
+async fn unavailable_scrapes_keep_summary_window() {
+    let chip = chip(reading(20.0));
+    let config = Config {
+        exporters: vec![Exporter::Readings, Exporter::Summary],
+        on_failure: FailureResponse::Unavailable,
+        ..test_config()
+    };
+    let addr = serve(vec![("flaky", chip.clone())], config).await;
+
+    scrape_until(addr, |status, _| status == StatusCode::OK).await;
+
+    chip.set_reading(reading(30.0));
+    tokio::time::sleep(SAMPLE_INTERVAL * 3).await;
+    chip.set_connected(false);
+    tokio::time::sleep(MAX_STALENESS * 2).await;
+    let (status, _) = get(addr, "/metrics").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+    // The first scrape answered with metrics again still covers the spike.
+    chip.set_reading(reading(20.0));
+    chip.set_connected(true);
+    let (_, body) = scrape_until(addr, |status, _| status == StatusCode::OK).await;
+    assert!(near(
+        sample(
+            &body,
+            r#"meter_summary_temperature_max_celsius{sensor="flaky"}"#
+        ),
+        30.0,
+        0.05
+    ));
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::near;
+use prometheus_bme280_exporter::tendency::{PressureHistory, Tendency};
+
+const STEP: Duration = Duration::from_secs(5 * 60);
+
+/// A history sampled every five minutes for three hours, with the pressure
+/// given by `pressure` at each hour since the start.
+fn history(pressure: impl Fn(f64) -> f64) -> PressureHistory {
+    let start = Instant::now();
+    let mut history = PressureHistory::new();
+    for step in 0..=36 {
+        let hours = step as f64 / 12.0;
+        history.push(start + STEP * step, pressure(hours));
+    }
+    history
+}
+
+fn code(pressure: impl Fn(f64) -> f64) -> Option<u8> {
+    history(pressure).tendency().code
+}
+
+#[test]
+fn reports_changes() {
+    let tendency = history(|hours| 100000.0 + 50.0 * hours).tendency();
+
+    assert!(near(tendency.change_1h, 50.0, 1e-6));
+    assert!(near(tendency.change_3h, 150.0, 1e-6));
+    assert_eq!(tendency.code, Some(2));
+}
+
+#[test]
+fn needs_enough_history() {
+    let start = Instant::now();
+    let mut history = PressureHistory::new();
+    assert_eq!(history.tendency(), Tendency::default());
+
+    for step in 0..=12 {
+        history.push(start + STEP * step, 100000.0 - 10.0 * step as f64);
+    }
+    let tendency = history.tendency();
+    assert!(near(tendency.change_1h, -120.0, 1e-6));
+    assert_eq!(tendency.change_3h, None);
+    assert_eq!(tendency.code, None);
+}
+
+#[test]
+fn ignores_gaps_in_history() {
+    let start = Instant::now();
+    let mut history = PressureHistory::new();
+    history.push(start, 100000.0);
+    // Nothing was measured for two hours.
+    history.push(start + Duration::from_secs(2 * 3600), 100100.0);
+
+    assert_eq!(history.tendency().change_1h, None);
+}
+
+#[test]
+fn classifies_tendency() {
+    let base = 100000.0;
+    // Rising then falling back, higher
+    assert_eq!(
+        code(|h| base + 100.0 * h.min(1.5) - 40.0 * (h - 1.5).max(0.0)),
+        Some(0)
+    );
+    // Rising then steady
+    assert_eq!(code(|h| base + 100.0 * h.min(1.5)), Some(1));
+    // Steady then rising
+    assert_eq!(code(|h| base + 100.0 * (h - 1.5).max(0.0)), Some(3));
+    // Steady
+    assert_eq!(code(|h| base + h), Some(4));
+    // Falling then rising back, lower
+    assert_eq!(
+        code(|h| base - 100.0 * h.min(1.5) + 40.0 * (h - 1.5).max(0.0)),
+        Some(5)
+    );
+    // Falling then steady
+    assert_eq!(code(|h| base - 100.0 * h.min(1.5)), Some(6));
+    // Falling
+    assert_eq!(code(|h| base - 60.0 * h), Some(7));
+    // Steady then falling
+    assert_eq!(code(|h| base - 100.0 * (h - 1.5).max(0.0)), Some(8));
+}
+
+#[test]
+fn compares_with_latest_sample() {
+    let start = Instant::now();
+    let mut history = PressureHistory::new();
+    for second in 0..=3600 {
+        history.push(
+            start + Duration::from_secs(second),
+            100000.0 + second as f64 / 36.0,
+        );
+    }
+
+    assert!(near(history.tendency().change_1h, 100.0, 1e-6));
+}